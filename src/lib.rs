@@ -0,0 +1,2068 @@
+/*
+    The todo-rs library, everything the `todo` CLI does to a list without the printing
+
+    - "TodoList" reads, changes and writes a list through a "Storage" backend
+    - "FileStorage" keeps the list in a todo.txt file with a .bak backup for undo
+    - "TodoItem" is a single parsed line, "parse_items" turns list content into them
+*/
+
+use std::{
+    collections::{
+        HashMap, HashSet
+    }, fmt::{
+        self, Display, Formatter
+    }, fs::{
+        self, File, OpenOptions
+    }, io::{
+        BufRead, BufReader, Error, ErrorKind, Read, Result, Write
+    }, path::{
+        Path, PathBuf
+    }, sync::OnceLock, time::{
+        SystemTime, UNIX_EPOCH
+    }
+};
+
+// Where a list's content lives, `TodoList` does every change as a read followed by a single write
+pub trait Storage {
+    // Read the whole list, a list that doesn't exist yet reads as empty
+    fn read(&self) -> Result<String>;
+
+    // Replace the whole list with new content
+    fn write(&mut self, content: &str) -> Result<()>;
+
+    // Read the list a line at a time, so read-only passes never hold all of a large list in memory
+    // ^ Storage that keeps the content in memory anyway can stick with splitting `read`
+    fn lines(&self) -> Result<Box<dyn Iterator<Item = Result<String>> + '_>> {
+        let lines: Vec<Result<String>> = self.read()?.lines().map(|line| Ok(line.to_string())).collect();
+        Ok(Box::new(lines.into_iter()))
+    }
+
+    // Empty the list
+    fn clear(&mut self) -> Result<()> {
+        self.write("")
+    }
+
+    // Add content to the end of the list without rewriting the rest
+    fn append(&mut self, content: &str) -> Result<()> {
+        let current = self.read()?;
+        self.write(&(current + content))
+    }
+
+    // Hold off other writers until the returned lock is dropped, storage nobody else can reach needs no lock
+    fn lock(&self) -> Result<Option<File>> {
+        Ok(None)
+    }
+
+    // Revert the last write, storage without a backup has nothing to revert to
+    fn undo(&mut self) -> Result<()> {
+        Err(Error::new(ErrorKind::Unsupported, "This list keeps no backup to undo with"))
+    }
+}
+
+// Lets a backend picked at runtime, i.e. `Box<dyn Storage>`, back a `TodoList` too
+impl<S: Storage + ?Sized> Storage for Box<S> {
+    fn read(&self) -> Result<String> {
+        (**self).read()
+    }
+
+    fn write(&mut self, content: &str) -> Result<()> {
+        (**self).write(content)
+    }
+
+    fn lines(&self) -> Result<Box<dyn Iterator<Item = Result<String>> + '_>> {
+        (**self).lines()
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        (**self).clear()
+    }
+
+    fn append(&mut self, content: &str) -> Result<()> {
+        (**self).append(content)
+    }
+
+    fn lock(&self) -> Result<Option<File>> {
+        (**self).lock()
+    }
+
+    fn undo(&mut self) -> Result<()> {
+        (**self).undo()
+    }
+}
+
+// A list stored in a todo.txt file, every write first saves the old content to todo.txt.bak
+pub struct FileStorage {
+    path: PathBuf
+}
+
+impl FileStorage {
+    pub fn new(path: impl Into<PathBuf>) -> FileStorage {
+        FileStorage { path: path.into() }
+    }
+
+    // Get the path of the single level undo backup, i.e. `todo.txt.bak` next to `todo.txt`
+    pub fn backup_path(&self) -> PathBuf {
+        let mut backup = self.path.as_os_str().to_owned();
+        backup.push(".bak");
+        PathBuf::from(backup)
+    }
+
+    // Save the list content as it was before a mutating command writes to it
+    // ^ The backup holds the same items, so it gets the list's permissions rather than the default ones
+    fn backup(&self) -> Result<()> {
+        let backup = self.backup_path();
+        fs::write(&backup, self.read()?)?;
+        if let Ok(metadata) = fs::metadata(&self.path) {
+            fs::set_permissions(&backup, metadata.permissions())?;
+        }
+        Ok(())
+    }
+}
+
+impl Storage for FileStorage {
+    // Read the list content as a string, creating todo.txt if it doesn't exist yet
+    // ^ CRLF line endings are normalized to LF, so every rewrite of the file emits LF only
+    // ^ Content that isn't UTF-8 is an error naming the first bad line, rather than being rewritten lossily
+    fn read(&self) -> Result<String> {
+        let mut file = get_file(&self.path, FileMode::Read)?;
+        let mut bytes = Vec::new();
+
+        file.read_to_end(&mut bytes)?;
+        let mut file_content = String::from_utf8(bytes).map_err(|e| {
+            let line = e.as_bytes()[..e.utf8_error().valid_up_to()].iter().filter(|&&byte| byte == b'\n').count() + 1;
+            invalid_utf8_error(&self.path, line)
+        })?;
+
+        if file_content.contains('\r') {
+            file_content = file_content.replace("\r\n", "\n");
+        }
+
+        Ok(file_content)
+    }
+
+    // Stream todo.txt through a buffered reader, which drops CRLF line endings like `read` normalizes them
+    fn lines(&self) -> Result<Box<dyn Iterator<Item = Result<String>> + '_>> {
+        let file = get_file(&self.path, FileMode::Read)?;
+        Ok(Box::new(BufReader::new(file).lines().enumerate().map(|(number, line)| match line {
+            Err(e) if e.kind() == ErrorKind::InvalidData => Err(invalid_utf8_error(&self.path, number + 1)),
+            line => line
+        })))
+    }
+
+    // Back up the old content and atomically replace it with the new one
+    fn write(&mut self, content: &str) -> Result<()> {
+        self.backup()?;
+        write_list_atomic(&self.path, content)
+    }
+
+    // Clear list by setting the length of the file to 0
+    fn clear(&mut self) -> Result<()> {
+        self.backup()?;
+
+        let file = get_file(&self.path, FileMode::Truncate)?;
+        file.set_len(0)
+    }
+
+    // Append without a backup, used for done.txt which `undo` never restores
+    fn append(&mut self, content: &str) -> Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()
+    }
+
+    // Take an exclusive lock on `todo.txt.lock` next to the list, held until the returned file is dropped
+    // ^ Every command that rewrites the list holds it from reading the content to committing the new one, so
+    //   concurrent invocations queue up instead of losing each other's writes. The lock is advisory, it only
+    //   keeps out other todo-rs processes and not an editor writing to todo.txt directly
+    //   The list itself can't be locked since `write_list_atomic` renames a new file over it
+    fn lock(&self) -> Result<Option<File>> {
+        let mut lock_path = self.path.as_os_str().to_owned();
+        lock_path.push(".lock");
+
+        let file = OpenOptions::new().create(true).truncate(false).write(true).open(PathBuf::from(lock_path))?;
+        file.lock()?;
+        Ok(Some(file))
+    }
+
+    // Swap the backup back into place, running it twice redoes the undone command
+    // ^ The current content becomes the new backup, so the swap is just another write
+    fn undo(&mut self) -> Result<()> {
+        let backup = self.backup_path();
+        if !backup.exists() {
+            return Err(Error::new(ErrorKind::InvalidInput, "Nothing to undo yet, no backup of your TODO list exists"));
+        }
+
+        let backup_content = fs::read_to_string(&backup)?;
+        self.write(&backup_content)
+    }
+}
+
+// What `TodoList::add` did with its messages
+pub struct Added {
+    // The lines as they were stored, so confirmations show the normalized text and final indices
+    pub stored: Vec<String>,
+    // Messages skipped as duplicates, with the index of the pending item they matched
+    pub skipped: Vec<(String, usize)>
+}
+
+// A list kept in memory, for tests and previews that must never touch a file
+// ^ Every write keeps the previous content as the backup `undo` swaps back, like `FileStorage` does
+pub struct InMemoryStorage {
+    content: String,
+    backup: Option<String>
+}
+
+impl InMemoryStorage {
+    // Start out holding `content`, with nothing to undo yet
+    pub fn new(content: impl Into<String>) -> InMemoryStorage {
+        InMemoryStorage::with_backup(content, None)
+    }
+
+    // Start out holding `content`, with `backup` as what `undo` would restore
+    pub fn with_backup(content: impl Into<String>, backup: Option<String>) -> InMemoryStorage {
+        InMemoryStorage { content: content.into(), backup }
+    }
+}
+
+impl Storage for InMemoryStorage {
+    fn read(&self) -> Result<String> {
+        Ok(self.content.clone())
+    }
+
+    fn write(&mut self, content: &str) -> Result<()> {
+        self.backup = Some(std::mem::replace(&mut self.content, content.to_string()));
+        Ok(())
+    }
+
+    fn append(&mut self, content: &str) -> Result<()> {
+        self.content.push_str(content);
+        Ok(())
+    }
+
+    fn undo(&mut self) -> Result<()> {
+        match self.backup.take() {
+            Some(backup) => self.write(&backup),
+            None => Err(Error::new(ErrorKind::InvalidInput, "Nothing to undo yet, no backup of your TODO list exists"))
+        }
+    }
+}
+
+// What `TodoList::set_done` or `toggle` did with the requested indices, any index in none of the lists doesn't exist
+pub struct Marked {
+    // Items that were checked off
+    pub checked: Vec<usize>,
+    // Items that were unchecked
+    pub unchecked: Vec<usize>,
+    // Items that were already in the requested state, these were left untouched
+    pub unchanged: Vec<usize>,
+    // The next occurrences of checked off recurring items, with their new indices
+    pub recurring: Vec<TodoItem>,
+    // The index each requested item has after renumbering, the lists above use these, missing indices are left out
+    pub moved: HashMap<usize, usize>
+}
+
+// A TODO list on top of some storage, every method that changes it reads, rewrites and writes the whole list once
+pub struct TodoList {
+    storage: Box<dyn Storage>
+}
+
+impl TodoList {
+    pub fn new(storage: impl Storage + 'static) -> TodoList {
+        TodoList { storage: Box::new(storage) }
+    }
+
+    // Parse every item in the list
+    pub fn list(&self) -> Result<Vec<TodoItem>> {
+        Ok(parse_items(&self.storage.read()?))
+    }
+
+    // Parse the list one item at a time as it's read, notes included
+    pub fn items(&self) -> Result<Items<Box<dyn Iterator<Item = Result<String>> + '_>>> {
+        Ok(Items { lines: self.storage.lines()?, pending: None })
+    }
+
+    // Count the items in the list and how many of them are done, without holding the whole list in memory
+    pub fn count(&self) -> Result<(usize, usize)> {
+        let (mut total, mut done) = (0, 0);
+        for item in self.items()? {
+            total += 1;
+            if item?.done {
+                done += 1;
+            }
+        }
+        Ok((total, done))
+    }
+
+    // Get the list exactly as the storage holds it, comments and notes included
+    pub fn content(&self) -> Result<String> {
+        self.storage.read()
+    }
+
+    // Clear the entire list
+    pub fn clear(&mut self) -> Result<()> {
+        let _lock = self.storage.lock()?;
+        self.storage.clear()
+    }
+
+    // Revert the last change to the list
+    pub fn undo(&mut self) -> Result<()> {
+        let _lock = self.storage.lock()?;
+        self.storage.undo()
+    }
+
+    // Append to the list by writing to it, or insert before the item at `at` and renumber the ones after it
+    // ^ The first index is only found once, every message after it is numbered consecutively
+    //   Unless `allow_duplicate` is set, messages matching a pending item are skipped
+    pub fn add(&mut self, messages: &[String], at: Option<usize>, allow_duplicate: bool) -> Result<Added> {
+        if messages.iter().any(|message| message.trim().is_empty()) {
+            return Err(Error::new(ErrorKind::InvalidInput, "Cannot add an empty item"));
+        }
+
+        // Renumbering first means hand edited duplicates or gaps can never make a new item collide
+        // ^ Normalizing first keeps blank lines at the end of the file from ending up above the new items
+        let _lock = self.storage.lock()?;
+        let file_content = self.storage.read()?;
+        let mut new_content = normalize_content(&renumber_list(&file_content));
+        let next_index = find_next_index(&new_content);
+
+        // Positions past the end clamp to appending, the renumbered list has no gaps so inserted items take over `at`
+        let insert_at = at.filter(|&at| at < next_index).map(|at| at.max(1));
+        let first_index = insert_at.unwrap_or(next_index);
+
+        // Earlier messages count too, so `todo add a a` only adds one item
+        let mut pending: Vec<(String, usize)> = parse_items(&new_content)
+            .iter()
+            .filter(|item| !item.done)
+            .map(|item| (duplicate_key(item), item.index))
+            .collect();
+
+        // Messages go through the parser so imported lines keep their priority and done marker in place
+        let today = Date::today();
+        let mut stored = Vec::new();
+        let mut skipped = Vec::new();
+        for message in messages.iter().map(|message| collapse_whitespace(message)) {
+            let line = format!("{}. {}", first_index + stored.len(), message);
+            stored.push(match parse_item(&line) {
+                Some(mut item) => {
+                    let key = duplicate_key(&item);
+                    let duplicate = pending.iter().find(|(existing, _)| *existing == key);
+                    if let (Some((_, index)), false) = (duplicate, allow_duplicate) {
+                        skipped.push((message, *index));
+                        continue;
+                    }
+                    pending.push((key, item.index));
+
+                    item.created.get_or_insert(today);
+                    item.to_line()
+                },
+                None => line
+            });
+        }
+
+        if stored.is_empty() {
+            return Ok(Added { stored, skipped });
+        }
+
+        let formatted_message: String = stored.iter().map(|line| format!("{}\n", line)).collect();
+
+        if let Some(insert_at) = insert_at {
+            let mut inserted = String::new();
+            for line in new_content.lines() {
+                if parse_item(line).is_some_and(|item| item.index == insert_at) {
+                    inserted.push_str(&formatted_message);
+                }
+                inserted.push_str(line);
+                inserted.push('\n');
+            }
+
+            self.save(&renumber_list(&inserted))?;
+            return Ok(Added { stored, skipped });
+        }
+
+        new_content.push_str(&formatted_message);
+
+        self.save(&new_content)?;
+        Ok(Added { stored, skipped })
+    }
+
+    // Append already parsed items after the last one, with their notes, in a single write
+    // ^ For imports that carry more than a line can, i.e. JSON items with notes, their indices are reassigned
+    pub fn append_items(&mut self, items: &mut [TodoItem]) -> Result<()> {
+        if items.is_empty() {
+            return Ok(());
+        }
+
+        let _lock = self.storage.lock()?;
+        let file_content = self.storage.read()?;
+        let mut new_content = normalize_content(&renumber_list(&file_content));
+        let next_index = find_next_index(&new_content);
+
+        let today = Date::today();
+        for (offset, item) in items.iter_mut().enumerate() {
+            item.index = next_index + offset;
+            item.created.get_or_insert(today);
+            new_content.push_str(&item.to_line());
+            new_content.push('\n');
+            for note in &item.notes {
+                new_content.push_str(&format!("\t{}\n", note));
+            }
+        }
+
+        self.save(&new_content)
+    }
+
+    // Remove a list item by index, with hand edited duplicates only the first item with it
+    pub fn remove(&mut self, index_number: usize) -> Result<()> {
+        let _lock = self.storage.lock()?;
+        let file_content = self.storage.read()?;
+        let Some(&index_number) = renumbered_indices(&file_content, &[index_number]).get(&index_number) else {
+            return Err(not_found_error(&[index_number]));
+        };
+
+        let (new_content, _) = remove_items(&renumber_list(&file_content), |item| item.index == index_number);
+        self.save(&renumber_list(&new_content))
+    }
+
+    // Mark items as done by appending the done marker to the end, or unmark them
+    // ^ Checking off records today as the completion date, unchecking removes it again
+    //   Checking off a recurring item also appends its next occurrence, which is returned with its new index
+    //   Items already in the requested state are left alone, and if that's all of them nothing is written
+    pub fn set_done(&mut self, index_numbers: &[usize], done: bool) -> Result<Marked> {
+        self.mark(index_numbers, |_| done)
+    }
+
+    // Flip the done state of every item, checking off pending ones and unchecking done ones in a single write
+    pub fn toggle(&mut self, index_numbers: &[usize]) -> Result<Marked> {
+        self.mark(index_numbers, |item| !item.done)
+    }
+
+    // Put every item in the done state `target` picks for it, skipping the write if none of them change
+    fn mark(&mut self, index_numbers: &[usize], target: impl Fn(&TodoItem) -> bool) -> Result<Marked> {
+        let _lock = self.storage.lock()?;
+        let today = Date::today();
+        let file_content = self.storage.read()?;
+
+        // Renumbering first means every index reported back matches the list as it's written
+        let moved = renumbered_indices(&file_content, index_numbers);
+        let new_content = renumber_list(&file_content);
+        let wanted: HashSet<usize> = moved.values().copied().collect();
+
+        let (unchanged, to_change): (Vec<TodoItem>, Vec<TodoItem>) = parse_items(&new_content)
+            .into_iter()
+            .filter(|item| wanted.contains(&item.index))
+            .partition(|item| item.done == target(item));
+        let unchanged: Vec<usize> = unchanged.iter().map(|item| item.index).collect();
+        let to_change: Vec<usize> = to_change.iter().map(|item| item.index).collect();
+
+        // Only items whose state differs from their target are rewritten, so each of them flips
+        let (mut checked, mut unchecked, mut recurring) = (Vec::new(), Vec::new(), Vec::new());
+        let (new_content, changed) = rewrite_items(&new_content, &to_change, |item| {
+            if item.done {
+                item.completed = None;
+                unchecked.push(item.index);
+            } else {
+                item.completed = Some(today);
+                recurring.extend(next_occurrence(item, today));
+                checked.push(item.index);
+            }
+            item.done = !item.done;
+        });
+
+        // Nothing is written then, so the indices stay the ones in the file
+        if changed.is_empty() {
+            let unchanged = index_numbers.iter().filter(|index| moved.contains_key(index)).copied().collect();
+            let moved = moved.into_keys().map(|index| (index, index)).collect();
+            return Ok(Marked { checked, unchecked, unchanged, recurring, moved });
+        }
+
+        let mut new_content = normalize_content(&new_content);
+        let next_index = find_next_index(&new_content);
+        for (offset, item) in recurring.iter_mut().enumerate() {
+            item.index = next_index + offset;
+            new_content.push_str(&item.to_line());
+            new_content.push('\n');
+        }
+
+        self.save(&new_content)?;
+
+        Ok(Marked { checked, unchecked, unchanged, recurring, moved })
+    }
+
+    // Edit the text of an item by index, keeping its priority and done marker, returning the item as stored
+    pub fn edit(&mut self, index_number: usize, new_message: &str) -> Result<TodoItem> {
+        self.update_item(index_number, |item| item.text = collapse_whitespace(new_message))
+    }
+
+    // Add text to the end of an item by index, after a space, the metadata stays stored after it
+    pub fn append_text(&mut self, index_number: usize, text: &str) -> Result<TodoItem> {
+        self.update_item(index_number, |item| item.text = collapse_whitespace(&format!("{} {}", item.text, text)))
+    }
+
+    // Set or clear the priority of an item by index, returning its index after renumbering
+    pub fn set_priority(&mut self, index_number: usize, priority: Option<char>) -> Result<usize> {
+        Ok(self.update_item(index_number, |item| item.priority = priority)?.index)
+    }
+
+    // Set or clear the color label of an item by index, the color must be one of `LABEL_COLORS`
+    pub fn set_label(&mut self, index_number: usize, label: Option<&str>) -> Result<usize> {
+        if let Some(color) = label.filter(|color| !LABEL_COLORS.contains(color)) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("`{}` is not a label color, use one of {} or `none`", color, LABEL_COLORS.join(", "))
+            ));
+        }
+
+        Ok(self.update_item(index_number, |item| item.label = label.map(str::to_string))?.index)
+    }
+
+    // Set or clear the due date of an item by index
+    pub fn set_due_date(&mut self, index_number: usize, due: Option<Date>) -> Result<usize> {
+        Ok(self.update_item(index_number, |item| item.due = due)?.index)
+    }
+
+    // Insert a note line after the item and any notes it already has, returning its index after renumbering
+    pub fn add_note(&mut self, index_number: usize, note: &str) -> Result<usize> {
+        let note = collapse_whitespace(note);
+        if note.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, "Cannot add an empty note"));
+        }
+
+        let _lock = self.storage.lock()?;
+        let file_content = self.storage.read()?;
+        let Some(&index_number) = renumbered_indices(&file_content, &[index_number]).get(&index_number) else {
+            return Err(not_found_error(&[index_number]));
+        };
+
+        let mut new_content = String::new();
+        let mut inserting = false;
+
+        for line in renumber_list(&file_content).lines() {
+            if inserting && parse_note(line).is_none() {
+                new_content.push_str(&format!("\t{}\n", note));
+                inserting = false;
+            }
+            if parse_item(line).is_some_and(|item| item.index == index_number) {
+                inserting = true;
+            }
+            new_content.push_str(line);
+            new_content.push('\n');
+        }
+
+        if inserting {
+            new_content.push_str(&format!("\t{}\n", note));
+        }
+
+        self.save(&new_content)?;
+        Ok(index_number)
+    }
+
+    // Exchange two items and their notes, each position keeps its index so nothing else is renumbered
+    // ^ With hand edited duplicates an index names the first item with it, the one `renumbered_indices` picks
+    pub fn swap(&mut self, first: usize, second: usize) -> Result<()> {
+        let _lock = self.storage.lock()?;
+        let file_content = self.storage.read()?;
+        let lines: Vec<&str> = file_content.lines().collect();
+
+        // The range of lines holding an item followed by its notes
+        let find_block = |index_number: usize| {
+            let start = lines.iter().position(|line| parse_item(line).is_some_and(|item| item.index == index_number))?;
+            let notes = lines[start + 1..].iter().take_while(|line| parse_note(line).is_some()).count();
+            Some(start..start + notes + 1)
+        };
+
+        let (a, b) = match (find_block(first), find_block(second)) {
+            (Some(a), Some(b)) => if a.start <= b.start { (a, b) } else { (b, a) },
+            (a, b) => {
+                let missing: Vec<usize> = [(first, a), (second, b)].into_iter()
+                    .filter_map(|(index, block)| block.is_none().then_some(index))
+                    .collect();
+                return Err(not_found_error(&missing));
+            }
+        };
+
+        if a == b {
+            return Ok(());
+        }
+
+        // Only the text after `N.` moves, so each index stays where it was
+        let retarget = |block: &[&str], target: &str| -> Vec<String> {
+            let index = split_index(target).map_or(0, |(index, _)| index);
+            let mut moved = block.iter().map(|line| line.to_string()).collect::<Vec<String>>();
+            moved[0] = format!("{}.{}", index, strip_index(block[0]));
+            moved
+        };
+
+        let mut new_lines: Vec<String> = lines[..a.start].iter().map(|line| line.to_string()).collect();
+        new_lines.extend(retarget(&lines[b.clone()], lines[a.start]));
+        new_lines.extend(lines[a.end..b.start].iter().map(|line| line.to_string()));
+        new_lines.extend(retarget(&lines[a.clone()], lines[b.start]));
+        new_lines.extend(lines[b.end..].iter().map(|line| line.to_string()));
+
+        let new_content: String = new_lines.iter().map(|line| format!("{}\n", line)).collect();
+        self.save(&new_content)
+    }
+
+    // Replace every whole `+old` or `@old` word with the new tag in one pass, returning how many items changed
+    // ^ Tags match ignoring case like `list --project` does, and either name may include the sigil
+    pub fn rename_tag(&mut self, sigil: char, old: &str, new: &str) -> Result<usize> {
+        let old = old.strip_prefix(sigil).unwrap_or(old);
+        let new = new.strip_prefix(sigil).unwrap_or(new);
+        if old.is_empty() || new.is_empty() || new.contains(char::is_whitespace) {
+            return Err(Error::new(ErrorKind::InvalidInput, "Tags must be a single word, i.e. `todo rename-project work job`"));
+        }
+
+        let _lock = self.storage.lock()?;
+        let file_content = self.storage.read()?;
+        let mut new_content = String::new();
+        let mut renamed = 0;
+
+        for line in file_content.lines() {
+            match parse_item(line) {
+                Some(mut item) if has_tag(item.tags(sigil), old, sigil) => {
+                    item.text = item.text.split(' ')
+                        .map(|word| match word.strip_prefix(sigil) {
+                            Some(tag) if tag.eq_ignore_ascii_case(old) => format!("{}{}", sigil, new),
+                            _ => word.to_string()
+                        })
+                        .collect::<Vec<String>>()
+                        .join(" ");
+                    new_content.push_str(&item.to_line());
+                    renamed += 1;
+                },
+                _ => new_content.push_str(line)
+            }
+            new_content.push('\n');
+        }
+
+        if renamed > 0 {
+            self.save(&new_content)?;
+        }
+
+        Ok(renamed)
+    }
+
+    // Remove every done item in one pass and renumber the rest, returning the removed items
+    // ^ With `completed_before` only the items checked off before that date are removed
+    pub fn purge(&mut self, completed_before: Option<Date>) -> Result<Vec<TodoItem>> {
+        self.remove_where(|item| is_finished(item, completed_before))
+    }
+
+    // Remove every item `is_removed` picks in one pass and renumber the rest, returning the removed items
+    // ^ Nothing is written if no item matched
+    pub fn remove_where(&mut self, is_removed: impl FnMut(&TodoItem) -> bool) -> Result<Vec<TodoItem>> {
+        let _lock = self.storage.lock()?;
+        let file_content = self.storage.read()?;
+        let (new_content, removed) = remove_items(&file_content, is_removed);
+
+        if removed.is_empty() {
+            return Ok(removed);
+        }
+
+        self.save(&renumber_list(&new_content))?;
+
+        Ok(removed)
+    }
+
+    // Append every done item and its notes to done.txt without its index, then remove them from the list
+    // ^ With `completed_before` only the items checked off before that date are moved
+    // ^ done.txt is written first so an interrupted archive leaves a duplicate rather than losing an item
+    //   `undo` only restores the list, the archived lines stay in done.txt
+    pub fn archive(&mut self, archive: &mut dyn Storage, completed_before: Option<Date>) -> Result<usize> {
+        let _lock = self.storage.lock()?;
+        let file_content = self.storage.read()?;
+        let (new_content, archived) = remove_items(&file_content, |item| is_finished(item, completed_before));
+
+        if archived.is_empty() {
+            return Ok(0);
+        }
+
+        let mut lines = String::new();
+        for item in &archived {
+            lines.push_str(strip_index(&item.to_line()).trim_start());
+            lines.push('\n');
+            for note in &item.notes {
+                lines.push_str(&format!("\t{}\n", note));
+            }
+        }
+        archive.append(&lines)?;
+
+        self.save(&renumber_list(&new_content))?;
+
+        Ok(archived.len())
+    }
+
+    // Give every item a sequential index again after the file was edited by hand, returning whether any changed
+    pub fn renumber(&mut self) -> Result<bool> {
+        let _lock = self.storage.lock()?;
+        let file_content = self.storage.read()?;
+        let new_content = renumber_list(&file_content);
+
+        if new_content == file_content {
+            return Ok(false);
+        }
+
+        self.save(&new_content)?;
+        Ok(true)
+    }
+
+    // Rewrite every item in the current format, i.e. legacy ` -s` suffixes as `[x]` checkboxes, returning how many changed
+    // ^ Comments and notes are kept as they are
+    pub fn migrate(&mut self) -> Result<usize> {
+        let _lock = self.storage.lock()?;
+        let file_content = self.storage.read()?;
+        let mut new_content = String::new();
+        let mut migrated = 0;
+
+        for line in file_content.lines() {
+            let rewritten = parse_item(line).map(|item| item.to_line()).filter(|rewritten| rewritten != line);
+            if rewritten.is_some() {
+                migrated += 1;
+            }
+            new_content.push_str(rewritten.as_deref().unwrap_or(line));
+            new_content.push('\n');
+        }
+
+        if migrated > 0 {
+            self.save(&new_content)?;
+        }
+
+        Ok(migrated)
+    }
+
+    // Find the items whose text contains the query, ignoring case
+    pub fn search(&self, query: &str) -> Result<Vec<TodoItem>> {
+        let query = query.to_lowercase();
+        let mut matches = Vec::new();
+        for item in self.items()? {
+            let item = item?;
+            if item.display_text().to_lowercase().contains(&query) {
+                matches.push(item);
+            }
+        }
+        Ok(matches)
+    }
+
+    // Find the indices of the items whose text matches exactly, after trimming
+    // ^ The text is compared without its priority, dates and done marker, so `index-of` works on what `add` was given
+    pub fn index_of(&self, text: &str) -> Result<Vec<usize>> {
+        let text = text.trim();
+        let mut found = Vec::new();
+        for item in self.items()? {
+            let item = item?;
+            if item.text.trim() == text {
+                found.push(item.index);
+            }
+        }
+        Ok(found)
+    }
+
+    // Rewrite the item with the given index, leaving every other line untouched, returning it as it was stored
+    fn update_item(&mut self, index_number: usize, update: impl FnMut(&mut TodoItem)) -> Result<TodoItem> {
+        match self.update_items(&[index_number], update)?.into_iter().next() {
+            Some(item) => Ok(item),
+            None => Err(not_found_error(&[index_number]))
+        }
+    }
+
+    // Write new content for the list, normalized so it always ends in exactly one newline
+    fn save(&mut self, content: &str) -> Result<()> {
+        self.storage.write(&normalize_content(content))
+    }
+
+    // Rewrite every item with one of the given indices in a single pass, returning the updated items
+    // ^ The list is renumbered first so the returned items carry the indices they're stored with
+    //   The file is only written if its content actually changed, reporting missing ones is left to the caller
+    fn update_items(&mut self, index_numbers: &[usize], update: impl FnMut(&mut TodoItem)) -> Result<Vec<TodoItem>> {
+        let _lock = self.storage.lock()?;
+        let file_content = self.storage.read()?;
+        let moved: Vec<usize> = renumbered_indices(&file_content, index_numbers).into_values().collect();
+        let (new_content, found) = rewrite_items(&renumber_list(&file_content), &moved, update);
+
+        if found.is_empty() || new_content == file_content {
+            return Ok(found);
+        }
+
+        self.save(&new_content)?;
+
+        Ok(found)
+    }
+}
+
+// A single parsed line of the TODO list, i.e. `3. [x] (A) buy milk`
+#[derive(Debug)]
+pub struct TodoItem {
+    pub index: usize,
+    pub text: String,
+    pub done: bool,
+    pub priority: Option<char>,
+    pub due: Option<Date>,
+    // One of `LABEL_COLORS`, stored as `col:red`
+    pub label: Option<String>,
+    // Stored as `created:YYYY-MM-DD` and `done:YYYY-MM-DD`, in local time
+    pub created: Option<Date>,
+    pub completed: Option<Date>,
+    // `+project` and `@context` tags, these stay inline in `text`
+    pub projects: Vec<String>,
+    pub contexts: Vec<String>,
+    // Tab indented lines right below the item, only filled in by `parse_items`
+    pub notes: Vec<String>
+}
+
+impl TodoItem {
+    // The text as it should be shown, with the backslashes escaping literal words removed
+    pub fn display_text(&self) -> String {
+        unescape_text(&self.text)
+    }
+
+    // The `+project` or `@context` tags of the item, depending on the sigil
+    pub fn tags(&self, sigil: char) -> &[String] {
+        if sigil == '+' { &self.projects } else { &self.contexts }
+    }
+
+    // Whether the item is still pending past its due date
+    pub fn is_overdue(&self, today: Date) -> bool {
+        !self.done && self.due.is_some_and(|due| due < today)
+    }
+
+    // Serialize the item back into the line stored in todo.txt, every command that rewrites an item goes through here
+    // ^ The order is always `N. [x] (A) text due:... col:... created:... done:...`, with `+project` and `@context`
+    //   tags left where they are inside the text, so rewriting an item never reshuffles an unchanged line
+    //   Words in the text that would be read back as a second copy of a field are escaped, which keeps
+    //   parsing and serializing again byte for byte stable
+    pub fn to_line(&self) -> String {
+        let mut line = format!("{}. ", self.index);
+        if self.done && *done_marker() == DoneMarker::Checkbox {
+            line.push_str("[x] ");
+        }
+        if let Some(priority) = self.priority {
+            line.push_str(&format!("({}) ", priority));
+        }
+        line.push_str(&self.literal_text());
+        if let Some(due) = self.due {
+            line.push_str(&format!(" due:{}", due));
+        }
+        if let Some(label) = &self.label {
+            line.push_str(&format!(" col:{}", label));
+        }
+        if let Some(created) = self.created {
+            line.push_str(&format!(" created:{}", created));
+        }
+        if let Some(completed) = self.completed {
+            line.push_str(&format!(" done:{}", completed));
+        }
+        if let (true, DoneMarker::Suffix(suffix)) = (self.done, done_marker()) {
+            line.push_str(suffix);
+        }
+        line
+    }
+
+    // The text with every word escaped that parsing the serialized line would take for metadata
+    // ^ Only fields the item already has count, a `due:` word in text is still picked up the first time,
+    //   i.e. after `todo edit 1 'call bob due:2025-06-01'`, and moved to its place on the next rewrite
+    fn literal_text(&self) -> String {
+        let taken = |word: &str| {
+            [("due:", self.due), ("created:", self.created), ("done:", self.completed)].iter()
+                .any(|(key, field)| field.is_some() && word.strip_prefix(key).and_then(Date::parse).is_some())
+                || (self.label.is_some() && word.strip_prefix("col:").is_some_and(|color| LABEL_COLORS.contains(&color)))
+        };
+
+        let mut words: Vec<String> = self.text.split(' ')
+            .map(|word| if taken(word) { format!("\\{}", word) } else { word.to_string() })
+            .collect();
+
+        // A pending item's text can't end in a done suffix, it would check the item off when read back
+        if !self.done && done_suffix(&format!(" {}", words.join(" "))).is_some() {
+            if let Some(last) = words.last_mut() {
+                last.insert(0, '\\');
+            }
+        }
+
+        // Nor can text start with what `to_line` would have written in front of it, i.e. `todo edit 1 '[x] foo'`
+        // ^ A written priority shields the text from both, a written checkbox only from another checkbox
+        let line = words.join(" ");
+        let checkbox = self.done && *done_marker() == DoneMarker::Checkbox;
+        let exposed = self.priority.is_none() && (split_priority(&line).0.is_some() || (!checkbox && split_checkbox(&line).is_some()));
+        if let (true, Some(first)) = (exposed, words.first_mut()) {
+            first.insert(0, '\\');
+        }
+        words.join(" ")
+    }
+}
+
+// Parse list content into items, skipping lines without an index and attaching notes to their item
+pub fn parse_items(content: &str) -> Vec<TodoItem> {
+    let mut items: Vec<TodoItem> = Vec::new();
+
+    for line in content.lines() {
+        match (parse_note(line), items.last_mut()) {
+            (Some(note), Some(item)) => item.notes.push(note.to_string()),
+            _ => items.extend(parse_item(line))
+        }
+    }
+
+    items
+}
+
+// Items parsed from a stream of lines, see `TodoList::items`
+// ^ Each item is held back until the next item line shows it has no more notes, like `parse_items` attaches them
+pub struct Items<I> {
+    lines: I,
+    pending: Option<TodoItem>
+}
+
+impl<I: Iterator<Item = Result<String>>> Iterator for Items<I> {
+    type Item = Result<TodoItem>;
+
+    fn next(&mut self) -> Option<Result<TodoItem>> {
+        loop {
+            let line = match self.lines.next() {
+                Some(Ok(line)) => line,
+                Some(Err(e)) => return Some(Err(e)),
+                None => return self.pending.take().map(Ok)
+            };
+
+            match (parse_note(&line), self.pending.as_mut()) {
+                (Some(note), Some(item)) => item.notes.push(note.to_string()),
+                _ => {
+                    let previous = parse_item(&line).and_then(|item| self.pending.replace(item));
+                    if previous.is_some() {
+                        return previous.map(Ok);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Parse a single line into an item, returning None if it has no index
+// ^ Blank lines, `#` comments and any other line without an index aren't items, `list` skips them
+//   but every rewrite of the file passes them through untouched so hand edits are never lost
+pub fn parse_item(line: &str) -> Option<TodoItem> {
+    if line.trim_start().starts_with('#') || parse_note(line).is_some() {
+        return None;
+    }
+
+    let (index, rest) = split_index(line)?;
+
+    let (done, rest) = split_done_marker(rest.trim());
+    let (priority, text) = split_priority(rest);
+    let (due, text) = split_date_token(text, "due");
+    let (label, text) = split_label(&text);
+    let (created, text) = split_date_token(&text, "created");
+    let (completed, text) = split_date_token(&text, "done");
+    let projects = find_tags(&text, '+');
+    let contexts = find_tags(&text, '@');
+
+    Some(TodoItem { index, text, done, priority, due, label, created, completed, projects, contexts, notes: Vec::new() })
+}
+
+// Split the `N.` prefix off of a line, i.e. `12. v1.2 release` into 12 and ` v1.2 release`
+// ^ Only digits directly followed by a dot and a space count, so text like `v1.2 release`, `3.5 kg` or
+//   `3 apples. more` is never read as an index
+fn split_index(line: &str) -> Option<(usize, &str)> {
+    let line = line.trim_start_matches(' ');
+    let digits = line.bytes().take_while(u8::is_ascii_digit).count();
+    let rest = line[digits..].strip_prefix('.').filter(|rest| rest.is_empty() || rest.starts_with([' ', '\t']))?;
+
+    Some((line[..digits].parse().ok()?, rest))
+}
+
+// Strip a leading `N.` index off of a line, if it has one
+pub fn strip_index(line: &str) -> &str {
+    split_index(line).map_or(line, |(_, rest)| rest)
+}
+
+// Get the text of a note line, i.e. `\tremember to call ahead`, notes belong to the item above them
+fn parse_note(line: &str) -> Option<&str> {
+    line.strip_prefix('\t')
+}
+
+// Drop the backslash in front of escaped words, i.e. `call \@home` is shown as `call @home`
+// ^ A word starting with `\` is never read as metadata, so `\+1`, `\@`, `\due:2025-06-01`, `\(A)`, a
+//   leading `\[x]` or a trailing `\-s` stay literal text. Items keep the backslash in todo.txt, so parsing it
+//   again round-trips, and a literal leading backslash is written doubled, i.e. `\\n`
+pub fn unescape_text(text: &str) -> String {
+    text.split(' ')
+        .map(|word| word.strip_prefix('\\').unwrap_or(word))
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+// Escape every word of plain text that would be read as metadata, the reverse of `unescape_text`
+// ^ For text that arrives without its metadata, i.e. an imported JSON item, where `due:`, `col:`, `created:` and
+//   `done:` words, a leading `(A)` or `[x]` and a trailing done marker can only be literal text.
+//   `+project` and `@context` tags are left as they are, they live in the text anyway
+pub fn escape_text(text: &str) -> String {
+    let mut words: Vec<String> = collapse_whitespace(text)
+        .split(' ')
+        .map(|word| {
+            let literal = word.starts_with('\\') || ["due:", "col:", "created:", "done:"].iter().any(|key| word.starts_with(key));
+            if literal { format!("\\{}", word) } else { word.to_string() }
+        })
+        .collect();
+
+    // Checkboxes, priorities and done suffixes only count at either end, escaping one end can expose the other
+    loop {
+        let line = words.join(" ");
+        let (_, rest) = split_done_marker(&line);
+        let index = match rest.len() < line.len() {
+            true if line.ends_with(rest) => 0,
+            true => words.len() - 1,
+            false if split_priority(&line).0.is_some() => 0,
+            false => return line
+        };
+        words[index].insert(0, '\\');
+    }
+}
+
+// The done suffix a line ends with, the configured one or the legacy ` -s`
+fn done_suffix(line: &str) -> Option<&'static str> {
+    match done_marker() {
+        DoneMarker::Suffix(suffix) if line.ends_with(suffix.as_str()) => Some(suffix.as_str()),
+        _ => Some(LEGACY_DONE_SUFFIX).filter(|suffix| line.ends_with(suffix))
+    }
+}
+
+// Find every `+tag` or `@tag` token in an item's text, without its sigil
+fn find_tags(text: &str, sigil: char) -> Vec<String> {
+    text.split_whitespace()
+        .filter_map(|word| word.strip_prefix(sigil))
+        .filter(|tag| !tag.is_empty())
+        .map(|tag| tag.to_string())
+        .collect()
+}
+
+// Check if an item has a tag, ignoring case and an optional leading sigil
+pub fn has_tag(tags: &[String], wanted: &str, sigil: char) -> bool {
+    let wanted = wanted.strip_prefix(sigil).unwrap_or(wanted);
+    tags.iter().any(|tag| tag.eq_ignore_ascii_case(wanted))
+}
+
+// Pull the first valid `key:YYYY-MM-DD` token out of an item's text, i.e. `due:2025-06-01`
+fn split_date_token(text: &str, key: &str) -> (Option<Date>, String) {
+    let prefix = format!("{}:", key);
+    let mut date = None;
+    let mut words = Vec::new();
+
+    for word in text.split(' ') {
+        match word.strip_prefix(&prefix).and_then(Date::parse) {
+            Some(parsed) if date.is_none() => date = Some(parsed),
+            _ => words.push(word)
+        }
+    }
+
+    (date, words.join(" "))
+}
+
+// The colors an item can be labeled with, `col:` tokens with anything else are left as text
+pub const LABEL_COLORS: [&str; 6] = ["red", "green", "yellow", "blue", "magenta", "cyan"];
+
+// Pull the first `col:COLOR` token with a known color out of an item's text
+fn split_label(text: &str) -> (Option<String>, String) {
+    let mut label = None;
+    let mut words = Vec::new();
+
+    for word in text.split(' ') {
+        match word.strip_prefix("col:").filter(|color| LABEL_COLORS.contains(color)) {
+            Some(color) if label.is_none() => label = Some(color.to_string()),
+            _ => words.push(word)
+        }
+    }
+
+    (label, words.join(" "))
+}
+
+// Split a leading `(A)` priority token off of an item's text
+fn split_priority(text: &str) -> (Option<char>, &str) {
+    let bytes = text.as_bytes();
+    if bytes.len() >= 3 && bytes[0] == b'(' && bytes[1].is_ascii_uppercase() && bytes[2] == b')' {
+        let rest = &text[3..];
+        if rest.is_empty() || rest.starts_with(' ') {
+            return (Some(bytes[1] as char), rest.trim_start());
+        }
+    }
+    (None, text)
+}
+
+// How done items are written to todo.txt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DoneMarker {
+    // A `[x]` checkbox right after the index, i.e. `3. [x] buy milk`
+    Checkbox,
+    // A suffix after the metadata, including the space separating it from the text, i.e. `" -s"`
+    // ^ The leading space keeps it from matching text like "vitamin-s"
+    Suffix(String)
+}
+
+// The suffix older lists mark done items with, still read as done whichever marker is written
+const LEGACY_DONE_SUFFIX: &str = " -s";
+
+// How done items are written, a checkbox unless config.toml sets another `done_marker`
+static DONE_MARKER: OnceLock<DoneMarker> = OnceLock::new();
+
+// Set how done items are written, only the first call has an effect and it defaults to a checkbox
+pub fn set_done_marker(marker: DoneMarker) {
+    let _ = DONE_MARKER.set(marker);
+}
+
+fn done_marker() -> &'static DoneMarker {
+    DONE_MARKER.get().unwrap_or(&DoneMarker::Checkbox)
+}
+
+// Split the done marker off of an item's text, returning whether it was there
+// ^ Both a `[x]` checkbox and the suffix are read whichever one is written, so lists from before a change of
+//   `done_marker` keep working until `migrate` rewrites them. A `[ ]` checkbox is dropped and read as pending
+fn split_done_marker(line: &str) -> (bool, &str) {
+    let line = line.trim();
+    if let Some(checkbox) = split_checkbox(line) {
+        return checkbox;
+    }
+
+    match done_suffix(line) {
+        Some(suffix) => (true, line[..line.len() - suffix.len()].trim_end()),
+        None => (false, line)
+    }
+}
+
+// Split a leading `[x]`, `[X]` or `[ ]` checkbox off of a line, returning whether it was checked
+fn split_checkbox(line: &str) -> Option<(bool, &str)> {
+    [("[x]", true), ("[X]", true), ("[ ]", false)].into_iter().find_map(|(checkbox, done)| {
+        let rest = line.strip_prefix(checkbox).filter(|rest| rest.is_empty() || rest.starts_with(' '))?;
+        Some((done, rest.trim_start()))
+    })
+}
+
+// Drop blank lines from the end of list content and end it with exactly one newline, an empty list stays empty
+// ^ Every line is written back with its own `\n`, so blank lines at the end of a hand edited file would
+//   otherwise be kept forever and a missing final newline would glue the next item onto the last line
+fn normalize_content(content: &str) -> String {
+    let content = content.trim_end();
+    if content.is_empty() {
+        String::new()
+    } else {
+        format!("{}\n", content)
+    }
+}
+
+// Find the next index for the list
+fn find_next_index(content: &str) -> usize {
+    parse_items(content).iter().map(|item| item.index).max().unwrap_or(0) + 1
+}
+
+// Find the lines that aren't an item, a note, a `#` comment or blank, with their line number counting from 1
+// ^ These are kept as they are but never shown, usually an item whose `N.` index was lost while editing by hand
+pub fn malformed_lines(content: &str) -> Vec<(usize, &str)> {
+    content.lines()
+        .enumerate()
+        .filter(|(_, line)| {
+            !line.trim().is_empty() && !line.trim_start().starts_with('#') && parse_note(line).is_none() && parse_item(line).is_none()
+        })
+        .map(|(number, line)| (number + 1, line))
+        .collect()
+}
+
+// Trim a message and collapse every run of spaces, tabs and newlines inside it into one space
+// ^ The list is line based, so a newline inside an argument would otherwise split it into two lines
+pub fn collapse_whitespace(message: &str) -> String {
+    message.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+// Whether `purge` and `archive` should take an item, every done item or only those checked off before a cutoff
+// ^ With a cutoff, done items without a `done:` date are kept since how long ago they were finished is unknown
+fn is_finished(item: &TodoItem, completed_before: Option<Date>) -> bool {
+    match completed_before {
+        Some(cutoff) => item.done && item.completed.is_some_and(|completed| completed < cutoff),
+        None => item.done
+    }
+}
+
+// The text two items are compared by when looking for duplicates, without priority, dates or done marker
+fn duplicate_key(item: &TodoItem) -> String {
+    item.text.trim().to_lowercase()
+}
+
+// Rewrite every item with sequential indices starting at 1, keeping order and done markers
+fn renumber_list(content: &str) -> String {
+    let mut new_content = String::new();
+    let mut next_index = 1;
+
+    for line in content.lines() {
+        match split_index(line) {
+            Some((_, rest)) if parse_item(line).is_some() => {
+                new_content.push_str(&format!("{}. {}\n", next_index, rest.trim_start()));
+                next_index += 1;
+            },
+            _ => {
+                new_content.push_str(line);
+                new_content.push('\n');
+            }
+        }
+    }
+
+    new_content
+}
+
+// Map each of the given indices to the one `renumber_list` gives its item, indices without an item are left out
+// ^ With hand edited duplicates the first item with an index is the one it refers to
+fn renumbered_indices(content: &str, index_numbers: &[usize]) -> HashMap<usize, usize> {
+    let wanted: HashSet<usize> = index_numbers.iter().copied().collect();
+    let mut moved = HashMap::new();
+
+    for (position, item) in parse_items(content).iter().enumerate() {
+        if wanted.contains(&item.index) {
+            moved.entry(item.index).or_insert(position + 1);
+        }
+    }
+
+    moved
+}
+
+// Drop every item matching `is_removed` along with its notes, returning the remaining content and the removed items
+fn remove_items(content: &str, mut is_removed: impl FnMut(&TodoItem) -> bool) -> (String, Vec<TodoItem>) {
+    let mut new_content = String::new();
+    let mut removed: Vec<TodoItem> = Vec::new();
+    let mut removing_notes = false;
+
+    for line in content.lines() {
+        match (parse_note(line), removed.last_mut()) {
+            (Some(note), Some(item)) if removing_notes => {
+                item.notes.push(note.to_string());
+                continue;
+            },
+            _ => ()
+        }
+
+        match parse_item(line) {
+            Some(item) if is_removed(&item) => {
+                removed.push(item);
+                removing_notes = true;
+            },
+            _ => {
+                new_content.push_str(line);
+                new_content.push('\n');
+                removing_notes = false;
+            }
+        }
+    }
+
+    (new_content, removed)
+}
+
+// Apply `update` to every item with one of the given indices, returning the new content and the updated items
+fn rewrite_items(content: &str, index_numbers: &[usize], mut update: impl FnMut(&mut TodoItem)) -> (String, Vec<TodoItem>) {
+    let mut new_content = String::new();
+    let mut found = Vec::new();
+    let wanted: HashSet<usize> = index_numbers.iter().copied().collect();
+
+    for line in content.lines() {
+        match parse_item(line) {
+            Some(mut item) if wanted.contains(&item.index) => {
+                update(&mut item);
+                new_content.push_str(&item.to_line());
+                found.push(item);
+            },
+            _ => new_content.push_str(line)
+        }
+        new_content.push('\n');
+    }
+
+    (new_content, found)
+}
+
+// The error for indices that don't match any item, printed by `main` before exiting non-zero
+pub fn not_found_error(index_numbers: &[usize]) -> Error {
+    let message = match index_numbers {
+        [index_number] => format!("Item with index {} not found.", index_number),
+        _ => format!("Items with indices {} not found.", join_indices(index_numbers))
+    };
+    Error::new(ErrorKind::InvalidInput, message)
+}
+
+// Join indices for a confirmation message, i.e. `1, 3, 5`
+pub fn join_indices(indices: &[usize]) -> String {
+    indices.iter().map(|index| index.to_string()).collect::<Vec<String>>().join(", ")
+}
+
+// Build the pending copy of a recurring item checked off `today`, or None if it has no `rec:` token
+// ^ The copy is left unnumbered, `mark_as_done` numbers it when appending it
+fn next_occurrence(item: &TodoItem, today: Date) -> Option<TodoItem> {
+    let recurrence = item.text.split(' ').find_map(Recurrence::parse)?;
+    let from = if recurrence.strict { item.due.unwrap_or(today) } else { today };
+
+    Some(TodoItem {
+        index: 0,
+        text: item.text.clone(),
+        done: false,
+        priority: item.priority,
+        due: Some(recurrence.next_due(from)),
+        label: item.label.clone(),
+        created: Some(today),
+        completed: None,
+        projects: item.projects.clone(),
+        contexts: item.contexts.clone(),
+        notes: Vec::new()
+    })
+}
+
+// How often an item comes back after it's checked off, from a `rec:1d`, `rec:2w` or `rec:1m` token in its text
+// ^ `rec:+1w` counts from the old due date instead of the day it was checked off, so a late chore doesn't drift
+#[derive(Debug, Clone, Copy)]
+struct Recurrence {
+    amount: u32,
+    unit: RecurrenceUnit,
+    strict: bool
+}
+
+#[derive(Debug, Clone, Copy)]
+enum RecurrenceUnit {
+    Day,
+    Week,
+    Month
+}
+
+impl Recurrence {
+    // Parse a single `rec:` word, the token stays inline in the item's text like tags do
+    fn parse(word: &str) -> Option<Recurrence> {
+        let value = word.strip_prefix("rec:")?;
+        let (strict, value) = match value.strip_prefix('+') {
+            Some(value) => (true, value),
+            None => (false, value)
+        };
+
+        let unit = match value.chars().last()? {
+            'd' => RecurrenceUnit::Day,
+            'w' => RecurrenceUnit::Week,
+            'm' => RecurrenceUnit::Month,
+            _ => return None
+        };
+        let amount = value[..value.len() - 1].parse::<u32>().ok().filter(|&amount| amount > 0)?;
+
+        Some(Recurrence { amount, unit, strict })
+    }
+
+    // The due date one interval after `from`
+    fn next_due(self, from: Date) -> Date {
+        match self.unit {
+            RecurrenceUnit::Day => from.add_days(self.amount.into()),
+            RecurrenceUnit::Week => from.add_days(i64::from(self.amount) * 7),
+            RecurrenceUnit::Month => from.add_months(self.amount)
+        }
+    }
+}
+
+// A calendar date without a timezone, i.e. `2025-06-01`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32
+}
+
+impl Date {
+    // Parse a `YYYY-MM-DD` date, rejecting days that don't exist like `2025-02-30`
+    pub fn parse(value: &str) -> Option<Date> {
+        let mut parts = value.splitn(3, '-');
+        let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+
+        if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+            return None;
+        }
+        if !(year.bytes().chain(month.bytes()).chain(day.bytes()).all(|b| b.is_ascii_digit())) {
+            return None;
+        }
+
+        let date = Date { year: year.parse().ok()?, month: month.parse().ok()?, day: day.parse().ok()? };
+        if date.month == 0 || date.month > 12 || date.day == 0 || date.day > days_in_month(date.year, date.month) {
+            return None;
+        }
+
+        Some(date)
+    }
+
+    // Today's date in the local time zone, or in UTC where the offset can't be looked up
+    pub fn today() -> Date {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs() as i64);
+
+        Date::from_days((seconds + utc_offset(seconds)).div_euclid(86_400))
+    }
+
+    // Convert days since 1970-01-01 into a date, see http://howardhinnant.github.io/date_algorithms.html
+    pub fn from_days(days: i64) -> Date {
+        let days = days + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days.rem_euclid(146_097);
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+
+        let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+        let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u32;
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        Date { year, month, day }
+    }
+
+    // Convert the date into days since 1970-01-01, the inverse of `from_days`
+    pub fn to_days(self) -> i64 {
+        let year = if self.month <= 2 { self.year - 1 } else { self.year };
+        let era = year.div_euclid(400);
+        let year_of_era = year.rem_euclid(400);
+        let shifted_month = if self.month > 2 { self.month - 3 } else { self.month + 9 } as i64;
+        let day_of_year = (153 * shifted_month + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+        era * 146_097 + day_of_era - 719_468
+    }
+
+    // The date `days` days later
+    pub fn add_days(self, days: i64) -> Date {
+        Date::from_days(self.to_days() + days)
+    }
+
+    // The same day `months` months later, clamped to the end of shorter months, i.e. 01-31 becomes 02-28
+    pub fn add_months(self, months: u32) -> Date {
+        let month_index = self.year * 12 + i64::from(self.month - 1) + i64::from(months);
+        let (year, month) = (month_index.div_euclid(12), month_index.rem_euclid(12) as u32 + 1);
+
+        Date { year, month, day: self.day.min(days_in_month(year, month)) }
+    }
+
+    // The day of the week counted from Monday, so Monday is 0 and Sunday is 6
+    pub fn weekday(self) -> u32 {
+        // 1970-01-01 was a Thursday
+        (self.to_days() + 3).rem_euclid(7) as u32
+    }
+
+    // Resolve a date relative to `today`, i.e. `today`, `tomorrow`, `+3d`, `+2w`, `+1m`, `friday` or `next monday`
+    // ^ A weekday on its own or after `next` is always the first one after today, never today itself
+    pub fn parse_relative(value: &str, today: Date) -> Option<Date> {
+        let value = value.trim().to_lowercase();
+        match value.as_str() {
+            "today" => return Some(today),
+            "tomorrow" => return Some(today.add_days(1)),
+            _ => {}
+        }
+
+        if let Some(offset) = value.strip_prefix('+') {
+            let unit = offset.chars().last()?;
+            let amount: u32 = offset[..offset.len() - unit.len_utf8()].parse().ok()?;
+            return match unit {
+                'd' => Some(today.add_days(amount.into())),
+                'w' => Some(today.add_days(i64::from(amount) * 7)),
+                'm' => Some(today.add_months(amount)),
+                _ => None
+            };
+        }
+
+        let name = value.strip_prefix("next ").unwrap_or(&value).trim();
+        let weekday = WEEKDAYS.iter().position(|day| *day == name || day[..3] == *name)? as u32;
+        let ahead = (weekday + 7 - today.weekday()) % 7;
+        Some(today.add_days(if ahead == 0 { 7 } else { ahead.into() }))
+    }
+}
+
+// Weekday names for `Date::parse_relative`, starting on Monday like `Date::weekday`
+const WEEKDAYS: [&str; 7] = ["monday", "tuesday", "wednesday", "thursday", "friday", "saturday", "sunday"];
+
+// Seconds the local time zone is ahead of UTC at `seconds` past the epoch, as the C library's `localtime_r` sees it
+// ^ `time_t` and `struct tm` only have the same layout everywhere on 64-bit Unix, elsewhere dates stay in UTC
+#[cfg(all(unix, target_pointer_width = "64"))]
+fn utc_offset(seconds: i64) -> i64 {
+    use std::ffi::{c_char, c_int, c_long};
+
+    #[repr(C)]
+    struct Tm {
+        fields: [c_int; 9],
+        gmtoff: c_long,
+        zone: *const c_char
+    }
+
+    extern "C" {
+        fn localtime_r(time: *const i64, result: *mut Tm) -> *mut Tm;
+    }
+
+    let mut tm = Tm { fields: [0; 9], gmtoff: 0, zone: std::ptr::null() };
+    // SAFETY: both pointers are valid for the call, `localtime_r` only writes to `tm` and keeps neither
+    let result = unsafe { localtime_r(&seconds, &mut tm) };
+    if result.is_null() { 0 } else { tm.gmtoff as i64 }
+}
+
+#[cfg(not(all(unix, target_pointer_width = "64")))]
+fn utc_offset(_seconds: i64) -> i64 {
+    0
+}
+
+impl Display for Date {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+// Number of days in a month, accounting for leap years
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31
+    }
+}
+
+// How `get_file` opens the list, every mode creates todo.txt if it doesn't exist yet
+#[derive(Clone, Copy, Debug)]
+enum FileMode {
+    // Read the content, opened in append mode only because creating a file requires write access
+    Read,
+    // Empty the file for writing from the start
+    Truncate
+}
+
+// The error for a list that isn't valid UTF-8, i.e. a task pasted in from another encoding
+fn invalid_utf8_error(path: &Path, line: usize) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("{} isn't valid UTF-8, line {} has bytes in another encoding, re-save the file as UTF-8", path.display(), line)
+    )
+}
+
+// Get the file using OpenOptions with the permissions the mode needs
+// ^ In every command used, if todo.txt doesnt exist it will create it for them.
+fn get_file(path: &Path, mode: FileMode) -> Result<File> {
+    let mut options = OpenOptions::new();
+    options.create(true);
+
+    match mode {
+        FileMode::Read => options.read(true).append(true),
+        FileMode::Truncate => options.write(true).truncate(true)
+    };
+
+    options.open(path)
+}
+
+// Sync the directory holding a file, making a rename into it durable
+// ^ Only Unix can open a directory to sync it, elsewhere the rename itself has to do
+fn sync_parent_dir(path: &Path) -> Result<()> {
+    if !cfg!(unix) {
+        return Ok(());
+    }
+
+    match path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        Some(parent) => File::open(parent)?.sync_all(),
+        None => File::open(".")?.sync_all()
+    }
+}
+
+// Replace the list content by writing a temporary file next to it and renaming it into place
+// ^ Renaming is atomic, so an interrupted write leaves either the old or the new list, never an empty one
+//   The temporary file is flushed, synced to disk and its size checked before the rename, and on Unix the
+//   directory is synced after it, so once this returns Ok the new list survives a crash or power loss
+//   Any failure along the way is returned, so no success message is printed for a list that isn't on disk
+//   A symlinked list is resolved first so the rename replaces its target rather than the link, and the new
+//   file gets the old one's permissions, so a private `0600` list stays private
+fn write_list_atomic(path: &Path, content: &str) -> Result<()> {
+    let path = &fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let permissions = fs::metadata(path).ok().map(|metadata| metadata.permissions());
+
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+
+    let result = File::create(&temp_path)
+        .and_then(|mut file| {
+            if let Some(permissions) = &permissions {
+                file.set_permissions(permissions.clone())?;
+            }
+            file.write_all(content.as_bytes())?;
+            file.flush()?;
+            file.sync_all()?;
+
+            let written = file.metadata()?.len();
+            if written != content.len() as u64 {
+                return Err(Error::new(
+                    ErrorKind::WriteZero,
+                    format!("only {} of {} bytes reached the disk", written, content.len())
+                ));
+            }
+            Ok(())
+        })
+        .and_then(|_| fs::rename(&temp_path, path))
+        .and_then(|_| sync_parent_dir(path));
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Parse a single line that is known to be an item
+    fn item(line: &str) -> TodoItem {
+        parse_item(line).expect("Test lines are items")
+    }
+
+    #[test]
+    fn serializing_a_decorated_item_is_byte_stable() {
+        let line = "3. [x] (A) call bob +work @phone due:2025-06-01 col:red created:2025-05-01 done:2025-05-20";
+        assert_eq!(item(line).to_line(), line);
+        assert_eq!(item(&item(line).to_line()).to_line(), line);
+    }
+
+    #[test]
+    fn metadata_is_written_in_canonical_order() {
+        let line = "1. buy milk done:2025-05-20 col:blue created:2025-05-01 due:2025-06-01 (B)";
+        assert_eq!(item(line).to_line(), "1. buy milk (B) due:2025-06-01 col:blue created:2025-05-01 done:2025-05-20");
+    }
+
+    #[test]
+    fn leading_checkbox_and_priority_in_text_stay_literal() {
+        for text in ["[x] foo", "[X] foo", "[ ] foo", "(A) foo"] {
+            let mut pending = item("1. placeholder");
+            pending.text = text.to_string();
+            let stored = pending.to_line();
+            let read_back = item(&stored);
+
+            assert!(!read_back.done, "{} checked the item off", stored);
+            assert_eq!(read_back.priority, None, "{} set a priority", stored);
+            assert_eq!(unescape_text(&read_back.text), text);
+            assert_eq!(read_back.to_line(), stored);
+        }
+    }
+
+    #[test]
+    fn written_prefixes_shield_the_text() {
+        assert_eq!(item("1. (A) [x] foo").to_line(), "1. (A) [x] foo");
+        assert_eq!(item("1. [x] [ ] foo").to_line(), "1. [x] [ ] foo");
+        assert_eq!(item("1. [x] \\(B) foo").to_line(), "1. [x] \\(B) foo");
+    }
+
+    // A list kept in memory, starting out with `content`
+    fn list(content: &str) -> TodoList {
+        TodoList::new(InMemoryStorage::new(content))
+    }
+
+    #[test]
+    fn removing_an_item_renumbers_the_ones_after_it() {
+        let mut list = list("1. milk\n2. eggs\n\tfree range\n3. bread\n4. butter\n");
+        list.remove(2).unwrap();
+        assert_eq!(list.content().unwrap(), "1. milk\n2. bread\n3. butter\n");
+        assert_eq!(list.list().unwrap().iter().map(|item| item.index).collect::<Vec<usize>>(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn parse_item_reads_done_markers() {
+        assert!(item("1. milk -s").done);
+        assert!(item("1. [X] milk").done);
+        assert!(!item("1. [ ] milk").done);
+        assert!(!item("1. vitamin-s").done);
+        assert_eq!(item("1. [ ] milk").text, "milk");
+    }
+
+    #[test]
+    fn parse_item_keeps_invalid_metadata_as_text() {
+        let parsed = item("1. pay rent due:2025-02-30 col:purple (A)");
+        assert_eq!(parsed.due, None);
+        assert_eq!(parsed.label, None);
+        assert_eq!(parsed.priority, None);
+        assert_eq!(parsed.text, "pay rent due:2025-02-30 col:purple (A)");
+    }
+
+    #[test]
+    fn date_parse_rejects_days_that_dont_exist() {
+        assert_eq!(Date::parse("2024-02-29"), Some(Date { year: 2024, month: 2, day: 29 }));
+        for value in ["2025-02-29", "2025-13-01", "2025-00-10", "2025-04-31", "2025-6-01", "25-06-01", "2025-06-01x"] {
+            assert_eq!(Date::parse(value), None, "{} parsed", value);
+        }
+    }
+
+    #[test]
+    fn undo_restores_the_previous_content() {
+        let mut list = list("1. milk\n2. eggs\n");
+        list.remove(1).unwrap();
+        list.undo().unwrap();
+        assert_eq!(list.content().unwrap(), "1. milk\n2. eggs\n");
+    }
+
+    #[test]
+    fn adding_an_empty_item_fails_without_writing() {
+        let mut list = list("1. milk\n");
+        for message in ["", "   ", "\t\n"] {
+            let added = list.add(&["eggs".to_string(), message.to_string()], None, false);
+            assert_eq!(added.err().map(|error| error.kind()), Some(ErrorKind::InvalidInput));
+        }
+        assert_eq!(list.content().unwrap(), "1. milk\n");
+    }
+
+    // A date that is known to be valid
+    fn date(value: &str) -> Date {
+        Date::parse(value).expect("Test dates are valid")
+    }
+
+    #[test]
+    fn parse_item_reads_every_field() {
+        let parsed = item("4. [x] (B) call +bob @phone about +work due:2025-06-01 col:cyan created:2025-05-01 done:2025-05-20");
+        assert_eq!(parsed.index, 4);
+        assert!(parsed.done);
+        assert_eq!(parsed.priority, Some('B'));
+        assert_eq!(parsed.text, "call +bob @phone about +work");
+        assert_eq!(parsed.due, Some(date("2025-06-01")));
+        assert_eq!(parsed.label.as_deref(), Some("cyan"));
+        assert_eq!(parsed.created, Some(date("2025-05-01")));
+        assert_eq!(parsed.completed, Some(date("2025-05-20")));
+        assert_eq!(parsed.projects, ["bob", "work"]);
+        assert_eq!(parsed.contexts, ["phone"]);
+    }
+
+    #[test]
+    fn set_done_reports_every_index() {
+        let mut list = list("1. milk\n2. [x] eggs\n");
+        let marked = list.set_done(&[1, 2, 7], true).unwrap();
+
+        assert_eq!(marked.checked, [1]);
+        assert_eq!(marked.unchanged, [2]);
+        assert!(!marked.moved.contains_key(&7));
+        assert_eq!(list.content().unwrap(), format!("1. [x] milk done:{}\n2. [x] eggs\n", Date::today()));
+
+        let marked = list.set_done(&[1], false).unwrap();
+        assert_eq!(marked.unchecked, [1]);
+        assert_eq!(list.content().unwrap(), "1. milk\n2. [x] eggs\n");
+    }
+
+    #[test]
+    fn date_days_round_trip() {
+        assert_eq!(Date::from_days(0), date("1970-01-01"));
+        assert_eq!(date("2000-03-01").to_days(), 11_017);
+        for days in [-800_000, -1, 0, 59, 11_016, 20_000, 2_932_896] {
+            assert_eq!(Date::from_days(days).to_days(), days);
+        }
+    }
+
+    #[test]
+    fn not_found_error_names_every_index() {
+        assert_eq!(not_found_error(&[3]).to_string(), "Item with index 3 not found.");
+        assert_eq!(not_found_error(&[3, 5]).to_string(), "Items with indices 3, 5 not found.");
+    }
+
+    #[test]
+    fn parse_item_skips_lines_without_an_index() {
+        for line in ["milk", "# a comment", "\t1. a note", "1.milk", "x. milk", ""] {
+            assert!(parse_item(line).is_none(), "{:?} parsed as an item", line);
+        }
+    }
+
+    #[test]
+    fn malformed_lines_skips_items_notes_comments_and_blanks() {
+        let content = "1. milk\n\ta note\n# comment\n\nbuy eggs\n3. bread\n2- butter\n";
+        assert_eq!(malformed_lines(content), [(5, "buy eggs"), (7, "2- butter")]);
+    }
+
+    #[test]
+    fn lines_that_arent_items_survive_rewrites() {
+        let mut list = list("# groceries\n1. milk\nbuy eggs\n2. bread\n");
+        list.set_done(&[2], true).unwrap();
+        list.remove(1).unwrap();
+        assert_eq!(list.content().unwrap(), format!("# groceries\nbuy eggs\n1. [x] bread done:{}\n", Date::today()));
+    }
+
+    #[test]
+    fn duplicated_indices_are_never_reused() {
+        let mut list = list("1. a\n3. [x] b\n3. c\n4. d\n");
+        let added = list.add(&["e".to_string()], None, false).unwrap();
+        assert_eq!(added.stored, [format!("5. e created:{}", Date::today())]);
+        assert_eq!(list.list().unwrap().iter().map(|item| item.index).collect::<Vec<usize>>(), [1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn duplicated_indices_name_the_first_item() {
+        let mut removed = list("1. a\n3. b\n3. c\n4. d\n");
+        removed.remove(3).unwrap();
+        assert_eq!(removed.content().unwrap(), "1. a\n2. c\n3. d\n");
+
+        let mut noted = list("1. a\n3. b\n3. c\n");
+        assert_eq!(noted.add_note(3, "hi").unwrap(), 2);
+        assert_eq!(noted.content().unwrap(), "1. a\n2. b\n\thi\n3. c\n");
+
+        let mut swapped = list("1. a\n3. b\n3. c\n");
+        swapped.swap(1, 3).unwrap();
+        assert_eq!(swapped.content().unwrap(), "1. b\n3. a\n3. c\n");
+    }
+
+    #[test]
+    fn add_at_inserts_and_renumbers() {
+        let today = Date::today();
+        let mut list = list("1. a\n2. b\n3. c\n");
+        let added = list.add(&["new".to_string()], Some(1), false).unwrap();
+        assert_eq!(added.stored, [format!("1. new created:{}", today)]);
+        assert_eq!(list.content().unwrap(), format!("1. new created:{}\n2. a\n3. b\n4. c\n", today));
+    }
+
+    #[test]
+    fn add_at_past_the_end_appends() {
+        let today = Date::today();
+        let mut list = list("1. a\n2. b\n");
+        list.add(&["new".to_string()], Some(9), false).unwrap();
+        assert_eq!(list.content().unwrap(), format!("1. a\n2. b\n3. new created:{}\n", today));
+    }
+
+    #[test]
+    fn parse_items_attaches_notes_to_the_item_above() {
+        let items = parse_items("\torphan note\n1. milk\n\tthe oat one\n\tor soy\n# comment\n2. eggs\n");
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].notes, ["the oat one", "or soy"]);
+        assert!(items[1].notes.is_empty());
+    }
+
+    #[test]
+    fn checking_off_a_recurring_item_adds_the_next_one() {
+        let mut list = list("1. water plants rec:+1w due:2025-06-01\n");
+        let marked = list.set_done(&[1], true).unwrap();
+
+        assert_eq!(marked.recurring.len(), 1);
+        assert_eq!(marked.recurring[0].index, 2);
+        assert_eq!(marked.recurring[0].due, Some(date("2025-06-08")));
+        assert!(!marked.recurring[0].done);
+    }
+
+    #[test]
+    fn updates_report_renumbered_indices() {
+        let mut edited = list("1. a\n5. b\n");
+        assert_eq!(edited.edit(5, "bee").unwrap().to_line(), "2. bee");
+        assert_eq!(edited.content().unwrap(), "1. a\n2. bee\n");
+
+        let mut checked = list("1. a\n5. b\n");
+        let marked = checked.set_done(&[5], true).unwrap();
+        assert_eq!(marked.checked, [2]);
+        assert_eq!(marked.moved[&5], 2);
+    }
+
+    #[test]
+    fn escaped_words_round_trip() {
+        let text = "call @home due:2025-06-01 (A) -s";
+        let escaped = escape_text(text);
+        assert_eq!(escaped, "call @home \\due:2025-06-01 (A) \\-s");
+        assert_eq!(unescape_text(&escaped), text);
+
+        assert_eq!(escape_text("(A) [x] milk"), "\\(A) [x] milk");
+        assert_eq!(escape_text("[x] milk"), "\\[x] milk");
+    }
+
+    #[test]
+    fn swap_only_changes_the_two_items() {
+        let mut list = list("# chores\n1. (A) a due:2025-06-01\n\tnote a\n2. b\nstray line\n3. [x] c\n\tnote c\n\tmore c\n4. d\n");
+        list.swap(3, 1).unwrap();
+        assert_eq!(list.content().unwrap(), "# chores\n1. [x] c\n\tnote c\n\tmore c\n2. b\nstray line\n3. (A) a due:2025-06-01\n\tnote a\n4. d\n");
+
+        assert_eq!(list.swap(2, 8).unwrap_err().to_string(), "Item with index 8 not found.");
+    }
+
+    #[test]
+    fn text_may_contain_periods_and_digits() {
+        let parsed = item("1. v1.2 release");
+        assert_eq!((parsed.index, parsed.text.as_str()), (1, "v1.2 release"));
+
+        let mut list = list("");
+        list.add(&["2. tests pass".to_string(), "3.5 hours".to_string()], None, false).unwrap();
+        let texts: Vec<String> = list.list().unwrap().into_iter().map(|item| item.text).collect();
+        assert_eq!(texts, ["2. tests pass", "3.5 hours"]);
+    }
+
+    #[test]
+    fn add_skips_pending_duplicates() {
+        let mut list = list("1. milk\n");
+        let added = list.add(&["Milk".to_string()], None, false).unwrap();
+
+        assert!(added.stored.is_empty());
+        assert_eq!(added.skipped, [("Milk".to_string(), 1)]);
+        assert_eq!(list.content().unwrap(), "1. milk\n");
+    }
+
+    #[test]
+    fn added_whitespace_is_collapsed() {
+        assert_eq!(collapse_whitespace("  buy\tmilk  and\n eggs "), "buy milk and eggs");
+
+        let mut list = list("");
+        list.add(&["call \t bob  now".to_string()], None, false).unwrap();
+        assert_eq!(list.list().unwrap()[0].text, "call bob now");
+    }
+
+    #[test]
+    fn rename_tag_only_renames_whole_tags() {
+        let mut list = list("1. plan +work\n2. book +workshop\n3. call @Work +WORK\n4. nothing here\n");
+        assert_eq!(list.rename_tag('+', "+work", "job").unwrap(), 2);
+        assert_eq!(list.content().unwrap(), "1. plan +job\n2. book +workshop\n3. call @Work +job\n4. nothing here\n");
+
+        assert_eq!(list.rename_tag('@', "home", "house").unwrap(), 0);
+        assert_eq!(list.rename_tag('+', "work", "two words").unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn add_appends_numbered_items_with_a_created_date() {
+        let mut list = list("1. milk\n");
+        let added = list.add(&["eggs".to_string(), "bread".to_string()], None, false).unwrap();
+
+        let today = Date::today();
+        assert_eq!(added.stored, [format!("2. eggs created:{}", today), format!("3. bread created:{}", today)]);
+        assert_eq!(list.content().unwrap(), format!("1. milk\n2. eggs created:{}\n3. bread created:{}\n", today, today));
+    }
+
+    #[test]
+    fn remove_drops_notes_and_renumbers() {
+        let mut list = list("1. milk\n\tthe oat one\n2. eggs\n3. bread\n");
+        list.remove(1).unwrap();
+        assert_eq!(list.content().unwrap(), "1. eggs\n2. bread\n");
+
+        let error = list.remove(9).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert_eq!(list.content().unwrap(), "1. eggs\n2. bread\n");
+    }
+
+    #[test]
+    fn unchanged_items_are_never_written() {
+        let content = "1. [x] milk done:2025-05-20\n2. (A) eggs\n";
+        let mut list = list(content);
+        assert_eq!(list.set_done(&[1], true).unwrap().unchanged, [1]);
+        assert_eq!(list.set_done(&[2], false).unwrap().unchanged, [2]);
+        list.set_priority(2, Some('A')).unwrap();
+        list.edit(2, "eggs").unwrap();
+
+        // Every write keeps a backup, so with nothing written there's still nothing to undo
+        assert_eq!(list.content().unwrap(), content);
+        assert_eq!(list.undo().unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn repeated_changes_never_add_blank_lines() {
+        let today = Date::today();
+        let mut list = list("1. a\n\n\n");
+        list.add(&["b".to_string()], None, false).unwrap();
+        assert_eq!(list.content().unwrap(), format!("1. a\n2. b created:{}\n", today));
+
+        for _ in 0..3 {
+            list.add(&["c".to_string()], None, true).unwrap();
+            list.remove(3).unwrap();
+        }
+        assert_eq!(list.content().unwrap(), format!("1. a\n2. b created:{}\n", today));
+
+        let mut recurring = self::list("1. water rec:1d\n\n");
+        recurring.set_done(&[1], true).unwrap();
+        let mut appended = self::list("1. a\n\n");
+        appended.append_items(&mut [item("1. b")]).unwrap();
+        for content in [recurring.content().unwrap(), appended.content().unwrap()] {
+            assert!(!content.contains("\n\n"), "{:?} has a blank line", content);
+        }
+    }
+
+    #[test]
+    fn toggling_twice_restores_the_list() {
+        // Checking off stamps today's date, so only a done item checked off today comes back byte for byte
+        let content = format!("1. (A) milk due:2025-06-01\n2. [x] eggs done:{}\n3. bread +home\n", Date::today());
+        let mut list = list(&content);
+        let toggled = list.toggle(&[1, 2]).unwrap();
+        assert_eq!((toggled.checked, toggled.unchecked), (vec![1], vec![2]));
+        assert!(list.list().unwrap()[0].done);
+        assert!(!list.list().unwrap()[1].done);
+
+        list.toggle(&[1, 2]).unwrap();
+        assert_eq!(list.content().unwrap(), content);
+    }
+
+    #[test]
+    fn date_arithmetic() {
+        assert_eq!(date("2024-12-31").add_days(1), date("2025-01-01"));
+        assert_eq!(date("2025-01-31").add_months(1), date("2025-02-28"));
+        assert_eq!(date("2024-01-31").add_months(1), date("2024-02-29"));
+        assert_eq!(date("2025-11-15").add_months(3), date("2026-02-15"));
+        assert_eq!(date("1970-01-01").weekday(), 3);
+        assert_eq!(date("2025-06-02").weekday(), 0);
+    }
+
+    #[test]
+    fn relative_dates() {
+        // A Wednesday
+        let today = date("2025-06-04");
+        assert_eq!(Date::parse_relative("today", today), Some(today));
+        assert_eq!(Date::parse_relative("Tomorrow", today), Some(date("2025-06-05")));
+        assert_eq!(Date::parse_relative("+3d", today), Some(date("2025-06-07")));
+        assert_eq!(Date::parse_relative("+2w", today), Some(date("2025-06-18")));
+        assert_eq!(Date::parse_relative("+1m", today), Some(date("2025-07-04")));
+        assert_eq!(Date::parse_relative("friday", today), Some(date("2025-06-06")));
+        assert_eq!(Date::parse_relative("next mon", today), Some(date("2025-06-09")));
+        assert_eq!(Date::parse_relative("wednesday", today), Some(date("2025-06-11")));
+        assert_eq!(Date::parse_relative("+3y", today), None);
+        assert_eq!(Date::parse_relative("someday", today), None);
+    }
+
+    // Storage that can only be read a line at a time, so anything reading the whole list panics
+    struct LinesOnly(&'static str);
+
+    impl Storage for LinesOnly {
+        fn read(&self) -> Result<String> {
+            panic!("read the whole list")
+        }
+
+        fn write(&mut self, _content: &str) -> Result<()> {
+            panic!("wrote the list")
+        }
+
+        fn lines(&self) -> Result<Box<dyn Iterator<Item = Result<String>> + '_>> {
+            Ok(Box::new(self.0.lines().map(|line| Ok(line.to_string()))))
+        }
+    }
+
+    #[test]
+    fn counting_streams_the_list() {
+        let list = TodoList::new(LinesOnly("# chores\n1. milk\n\tthe oat one\n2. [x] eggs\n3. bread\n"));
+        assert_eq!(list.count().unwrap(), (3, 1));
+
+        let items: Vec<TodoItem> = list.items().unwrap().collect::<Result<_>>().unwrap();
+        assert_eq!(items.iter().map(|item| item.text.as_str()).collect::<Vec<_>>(), ["milk", "eggs", "bread"]);
+        assert_eq!(items[0].notes, ["the oat one"]);
+    }
+
+    #[test]
+    fn purging_with_a_cutoff_keeps_recent_and_undated_items() {
+        let cutoff = date("2025-06-10");
+        let mut list = list("1. [x] old done:2025-06-09\n2. [x] on the day done:2025-06-10\n3. [x] recent done:2025-06-11\n4. [x] undated\n5. pending\n");
+        let purged = list.purge(Some(cutoff)).unwrap();
+
+        assert_eq!(purged.iter().map(|item| item.text.as_str()).collect::<Vec<_>>(), ["old"]);
+        assert_eq!(list.content().unwrap(), "1. [x] on the day done:2025-06-10\n2. [x] recent done:2025-06-11\n3. [x] undated\n4. pending\n");
+
+        list.purge(None).unwrap();
+        assert_eq!(list.content().unwrap(), "1. pending\n");
+    }
+
+    #[test]
+    fn renumber_closes_gaps_and_keeps_other_lines() {
+        let mut list = list("# groceries\n3. milk\n\tthe oat one\n3. eggs\nbuy bread\n10. butter\n");
+        assert!(list.renumber().unwrap());
+        assert_eq!(list.content().unwrap(), "# groceries\n1. milk\n\tthe oat one\n2. eggs\nbuy bread\n3. butter\n");
+        assert!(!list.renumber().unwrap());
+    }
+
+    #[test]
+    fn invalid_utf8_names_the_bad_line() {
+        let dir = std::env::temp_dir().join(format!("todo-rs-utf8-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("todo.txt");
+        fs::write(&path, b"1. milk\n2. caf\xe9\n3. eggs\n").unwrap();
+
+        let storage = FileStorage::new(&path);
+        let expected = format!("{} isn't valid UTF-8, line 2 has bytes in another encoding, re-save the file as UTF-8", path.display());
+        let error = storage.read().unwrap_err();
+        assert_eq!((error.kind(), error.to_string()), (ErrorKind::InvalidData, expected.clone()));
+
+        let lines: Vec<Result<String>> = storage.lines().unwrap().collect();
+        assert_eq!(lines[0].as_ref().unwrap(), "1. milk");
+        let error = lines[1].as_ref().unwrap_err();
+        assert_eq!((error.kind(), error.to_string()), (ErrorKind::InvalidData, expected));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -2,25 +2,70 @@
     todo-rs is a CLI TODO list written in Rust
 
     - stored in todo.txt
+    - parsing and every change to the list live in the `todo` library (lib.rs), this file only handles the CLI
 
-    - "add" creates a new line in the TODO list with corresponding data
+    - "add" creates a new line in the TODO list for every message given
         - added with a prefix, i.e. '* "item"'
         - double quotes are added
     - "rm" finds the item in the list and removes it
-    - "done" adds "-s" as a suffix to the selected item, i.e. '* "item" -s'
-    - "list" parses this data and prints it out, i.e. '1. Item' or '1. Item -s" (replace -s with strikethrough)
+        - "--project" or "--matching" remove every item with that +project or containing that text instead
+    - "done" checks off the selected item with a '[x]' after its index, i.e. '1. [x] item'
+        - or with a suffix like '-s' if config.toml sets "done_marker", both forms are always read as done
+        - checking off an item with a 'rec:1d', 'rec:1w' or 'rec:1m' token adds its next occurrence
+    - "toggle" checks off pending items and unchecks done ones
+    - "swap" exchanges two items, leaving every other line as it was
+    - "rename-project" and "rename-context" rename a +project or @context tag on every item
+    - "edit" replaces the text of the selected item, keeping its done marker, priority and metadata
+    - "search" prints every item whose text contains the query, ignoring case
+    - "index-of" prints the index of every item whose text is exactly the given text
+    - "pri" sets a priority letter on the selected item, i.e. '1. (A) item'
+    - "due" sets a due date on the selected item, i.e. '1. item due:2025-06-01'
+    - "label" colors the selected item's text in "list", i.e. '1. item col:red'
+    - "note" attaches a note to the selected item, stored on a tab indented line beneath it
+    - "count" prints how many items are pending and done
+    - "pending-count" prints only the pending count for shell prompts, or nothing when it's 0
+    - "import" adds every line of a file, or stdin with '-', as a new item
+        - a JSON array of items, like "list --json" prints, is read with its metadata
+    - "export" prints every item's text with a '[x]' or '[ ]' checkbox and no indices
+    - "purge" removes every done item and renumbers the rest
+    - "archive" moves every done item to done.txt next to todo.txt (<name>.done.txt for other lists) and renumbers the rest
+    - "--dry-run" prints what any command would change as a diff instead of writing it
+    - "--stdin" reads the list from stdin and "--stdout" prints the changed list instead of writing the file
+    - "interactive" reads indices from stdin in a loop, toggling each one until 'q'
+    - "top" prints the single pending item to do next, by priority, due date and index
+    - "today" prints the pending items due today or overdue
+    - "stats" prints totals, the completion rate and overdue items
+    - "list" parses this data and prints it out, i.e. '1. Item' or '1. [x] Item' (replace [x] with strikethrough)
     - "clear" clears the entire todo.txt file
+    - "undo" swaps the todo.txt.bak backup, saved before every change, back into place
+    - "open" edits todo.txt in $EDITOR, then renumbers it and warns about lines that aren't items
+    - "migrate" rewrites every item with the current done marker, i.e. '1. item -s' as '1. [x] item'
+    - "info" prints where todo.txt and config.toml are and how many items the list has
+    - "lists" prints every named list and "use" switches the active one, stored in lists/<name>.txt
 */
 
-use clap::Parser;
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
 
 use std::{
-    env::current_exe, fs::{
-        File, OpenOptions
+    borrow::Borrow, cmp::Ordering, collections::HashSet, env::{
+        current_dir, current_exe, var_os
+    }, fs::{
+        self, create_dir_all, File
+    }, fmt::{
+        self, Display, Formatter
     }, io::{
-        Read, Result, Write
+        read_to_string, stdin, stdout, Error, ErrorKind, IsTerminal, Result, Write
     },
-    path::PathBuf
+    path::{
+        absolute, Path, PathBuf
+    }, process::{
+        exit, ExitCode, Stdio
+    }, sync::OnceLock
+};
+
+use todo::{
+    collapse_whitespace, escape_text, has_tag, join_indices, malformed_lines, not_found_error, parse_items, set_done_marker, strip_index, unescape_text,
+    Date, DoneMarker, FileStorage, Marked, InMemoryStorage, Storage, TodoItem, TodoList, LABEL_COLORS
 };
 
 use lazy_static::lazy_static;
@@ -32,259 +77,2989 @@ todo-rs is a CLI TODO list written in Rust for a
 super fast response time, utilizing the `clap` and `lazy_static`
 libraries.
 ------
-supported commands: add, rm, done, undone, list, clear";
+supported commands: add, rm, done, undone, toggle, swap, rename-project, rename-context, edit, pri, due, label, note, import, export, search, index-of, count, pending-count, top, today, stats, list, purge, archive, interactive, clear, undo, open, migrate, info, lists, use";
 
 #[derive(Parser, Debug)]
 #[command(version="1.0.0", long_about=ABOUT_MESSAGE)]
 struct CommandArguments {
-    command: String, // add, rm, done, list
-    #[clap(short='M', long="message", default_value="0", long_help="Add a message to your command, used for 'add'")]
-    message: String,
-    #[clap(short='I', long="index", default_value="0", long_help="Add an index value to your command, used for 'rm' and 'done'")]
-    index: String
+    #[command(subcommand)]
+    command: Command,
+    /// Use a different TODO list file for this command, defaults to the resolved todo.txt
+    #[arg(long, global = true, value_name = "PATH")]
+    file: Option<PathBuf>,
+    /// Use one of your named lists for this command instead of the active one, see `todo lists`
+    #[arg(long, global = true, value_name = "NAME", conflicts_with = "file")]
+    list: Option<String>,
+    /// When to color output, defaults to `color` from config.toml or `auto`, which only colors a terminal
+    #[arg(long, global = true, value_enum)]
+    color: Option<ColorChoice>,
+    /// Same as `--color always`, colors even when piped, i.e. into `less -R`
+    #[arg(long, global = true, conflicts_with = "color")]
+    force_color: bool,
+    /// Don't print confirmations for commands that change the list, errors are still printed
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Also print the resolved list path and item count to stderr after the command
+    #[arg(short, long, global = true)]
+    verbose: bool,
+    /// Print the lines a command would remove (`-`) and add (`+`) instead of changing any file
+    #[arg(long, global = true)]
+    dry_run: bool,
+    /// Read the whole list from stdin instead of a file, no list file is opened
+    #[arg(long, global = true, conflicts_with_all = ["file", "list"])]
+    stdin: bool,
+    /// Print the resulting list to stdout instead of writing it back, confirmations are left out
+    #[arg(long, global = true, conflicts_with = "dry_run")]
+    stdout: bool,
+    /// Print plain ASCII instead of emoji and symbols, also the default when NO_EMOJI is set or the locale isn't UTF-8
+    #[arg(long, global = true)]
+    no_emoji: bool
+}
+
+// Settings from global flags that change how every command prints and where the list is kept, resolved once in `main`
+struct Options {
+    color: bool,
+    quiet: bool,
+    verbose: bool,
+    dry_run: bool,
+    stdin: bool,
+    stdout: bool
+}
+
+impl Options {
+    // Print a confirmation for a command that changed the list, unless `--quiet` was given
+    fn confirm(&self, message: &str) {
+        if !self.quiet {
+            println!("{}", message);
+        }
+    }
+}
+
+// Values accepted by `--color`
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never
+}
+
+impl ColorChoice {
+    // Whether ANSI escape codes should be printed
+    fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => stdout().is_terminal(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Add one or more items to your TODO list
+    #[command(visible_alias = "a")]
+    Add {
+        /// The items to add, `-` reads one item's text from stdin so it needs no quoting
+        #[arg(required = true)]
+        messages: Vec<String>,
+        /// Insert at this position instead of appending, positions past the end append
+        #[arg(long, value_name = "INDEX", value_parser = parse_number_arg)]
+        at: Option<usize>,
+        /// Add items even if a pending item with the same text already exists
+        #[arg(long)]
+        allow_duplicate: bool
+    },
+    /// Remove an item from your TODO list by index, or `first`/`last` for the lowest or highest numbered one
+    #[command(visible_alias = "del")]
+    Rm {
+        #[arg(required_unless_present_any = ["project", "matching"], conflicts_with_all = ["project", "matching"])]
+        index: Option<String>,
+        /// Remove every item tagged with `+PROJECT` instead, asking for confirmation first
+        #[arg(long, conflicts_with = "matching")]
+        project: Option<String>,
+        /// Remove every item whose text contains TEXT instead, ignoring case and asking for confirmation first
+        #[arg(long, value_name = "TEXT")]
+        matching: Option<String>,
+        /// Don't ask before removing several items
+        #[arg(long)]
+        force: bool
+    },
+    /// Check off items by index, i.e. `todo done 1 3 5`, `todo done 2-4` or `todo done last`
+    #[command(visible_alias = "d")]
+    Done {
+        #[arg(required = true)]
+        indices: Vec<String>
+    },
+    /// Uncheck items by index, accepting the same indices and ranges as `done`
+    #[command(visible_alias = "u")]
+    Undone {
+        #[arg(required = true)]
+        indices: Vec<String>
+    },
+    /// Flip items between done and pending, accepting the same indices and ranges as `done`
+    Toggle {
+        #[arg(required = true)]
+        indices: Vec<String>
+    },
+    /// Exchange two items, keeping every other line exactly as it was
+    Swap {
+        first: String,
+        second: String
+    },
+    /// Rename a `+project` tag on every item that has it
+    #[command(name = "rename-project")]
+    RenameProject {
+        old: String,
+        new: String
+    },
+    /// Rename an `@context` tag on every item that has it
+    #[command(name = "rename-context")]
+    RenameContext {
+        old: String,
+        new: String
+    },
+    /// Change the text of an item by index
+    Edit {
+        index: String,
+        #[arg(required_unless_present = "append")]
+        message: Option<String>,
+        /// Add TEXT to the end of the item instead of replacing it
+        #[arg(long, value_name = "TEXT", conflicts_with = "message")]
+        append: Option<String>
+    },
+    /// Set the priority of an item, A-Z or `none` to clear it
+    Pri {
+        index: String,
+        priority: String
+    },
+    /// Set the due date of an item, YYYY-MM-DD, `today`, `tomorrow`, `+3d`, `+2w`, `+1m`, `next monday` or `none` to clear it
+    Due {
+        index: String,
+        /// Several words are joined, so `todo due 3 next friday` needs no quotes
+        #[arg(required = true, num_args = 1..)]
+        date: Vec<String>
+    },
+    /// Color an item's text in `list`: red, green, yellow, blue, magenta, cyan or `none` to clear it
+    Label {
+        index: String,
+        // Not `color`, that would clash with the global `--color`
+        #[arg(value_name = "COLOR")]
+        label: String
+    },
+    /// Attach a note to an item, shown beneath it by `list --notes`
+    Note {
+        index: String,
+        note: String
+    },
+    /// Add every line of a file as an item, or every object of a JSON array like `list --json` prints, use `-` to read from stdin
+    Import {
+        source: PathBuf,
+        /// How the input is read, by default a JSON array is recognized by its opening `[{` or `[]`
+        #[arg(long, value_enum)]
+        format: Option<ImportFormat>
+    },
+    /// Print every item's text without indices or colors, for piping elsewhere
+    Export {
+        #[arg(long, value_enum, default_value_t = ExportFormat::Plain)]
+        format: ExportFormat
+    },
+    /// Print the items containing some text, ignoring case
+    Search {
+        query: String,
+        /// Only show items that are checked off
+        #[arg(long, conflicts_with = "pending")]
+        done: bool,
+        /// Only show items that are not checked off
+        #[arg(long)]
+        pending: bool
+    },
+    /// Print the index of every item whose text is exactly the given text, one per line
+    #[command(name = "index-of")]
+    IndexOf {
+        text: String
+    },
+    /// Count the items in your TODO list
+    Count {
+        /// Only print the bare number of pending items, i.e. for a shell prompt
+        #[arg(long)]
+        pending: bool
+    },
+    /// Print only the number of pending items without a newline, and nothing at all when there are none
+    #[command(name = "pending-count")]
+    PendingCount,
+    /// Print pending items due today or overdue, overdue first
+    Today {
+        /// Also print pending items without a due date, after the dated ones
+        #[arg(long)]
+        include_undated: bool
+    },
+    /// Print the one pending item to do next, by priority, then due date, then index
+    Top {
+        /// How to print it, `text` leaves out the index and colors for status bars
+        #[arg(long, value_enum, default_value_t = TopFormat::Full)]
+        format: TopFormat
+    },
+    /// Print a summary of your TODO list with its completion rate
+    Stats {
+        /// Also count the items checked off on or after this date, YYYY-MM-DD
+        #[arg(long, value_name = "DATE", value_parser = parse_date_arg)]
+        since: Option<Date>,
+        /// Also count the items checked off on or before this date, YYYY-MM-DD
+        #[arg(long, value_name = "DATE", value_parser = parse_date_arg)]
+        until: Option<Date>
+    },
+    /// Print your TODO list
+    #[command(visible_alias = "ls")]
+    List(ListArgs),
+    /// Remove every checked off item and renumber the rest
+    Purge {
+        /// Only remove items checked off more than DAYS days ago, items without a `done:` date are kept
+        #[arg(long, value_name = "DAYS", value_parser = parse_number_arg)]
+        older_than: Option<usize>
+    },
+    /// Move every checked off item to done.txt (<name>.done.txt for named lists) next to your TODO list and renumber the rest
+    Archive {
+        /// Only move items checked off more than DAYS days ago, items without a `done:` date are kept
+        #[arg(long, value_name = "DAYS", value_parser = parse_number_arg)]
+        older_than: Option<usize>
+    },
+    /// Toggle items on and off by typing their indices, until an empty line or `q`
+    #[command(visible_alias = "i")]
+    Interactive,
+    /// Clear your entire TODO list, or only its checked off items with `--done`, asking for confirmation first
+    #[command(visible_alias = "wipe")]
+    Clear {
+        /// Clear without asking, required when stdin is not a terminal
+        #[arg(short, long)]
+        force: bool,
+        /// Only remove the checked off items and renumber the rest, like `purge`
+        #[arg(long)]
+        done: bool
+    },
+    /// Revert the last command that changed your TODO list
+    Undo,
+    /// Open your TODO list in $EDITOR, then renumber it and warn about lines that aren't items
+    #[command(visible_alias = "edit-file")]
+    Open,
+    /// Rewrite every item with the current done marker, i.e. old `-s` suffixes as `[x]` checkboxes
+    Migrate,
+    /// Print where your TODO list and config live and how many items the list has
+    Info,
+    /// Print your named lists, marking the one in use with `*`
+    Lists,
+    /// Make a named list the active one for every later command, `default` switches back
+    Use {
+        name: String
+    },
+    /// Print a tab-completion script, i.e. `todo completions zsh > _todo`
+    #[command(hide = true)]
+    Completions {
+        shell: Shell
+    }
 }
 
-fn main() {
+// Exit codes, clap also uses USAGE_ERROR when it rejects the arguments
+// ^ 0 on success, 1 when reading or writing the list fails, 2 for bad input like an unknown index
+//   `search` also exits with 1 when nothing matched, like grep
+const IO_ERROR: u8 = 1;
+const USAGE_ERROR: u8 = 2;
+const NO_MATCHES: u8 = 1;
+
+fn main() -> ExitCode {
     let args = CommandArguments::parse();
 
-    let command: String = args.command;
-    let message: String = args.message;
-    let index: String = args.index.to_string();
+    // Completions only introspect the parser, so don't resolve (and create) the data directory for them
+    if let Command::Completions { shell } = args.command {
+        print!("{}", generate_completions(shell));
+        return ExitCode::SUCCESS;
+    }
 
-    if !command.is_empty() {
-        match &command as &str {
-            "add" => {
-                append_to_list(&message);
-                println!("Added to your TODO list: {}", message);
-            },
+    // `--stdin` lists have no file, `archive` would have to write done.txt somewhere and `info` describes the file
+    if args.stdin && matches!(args.command, Command::Info | Command::Archive { .. } | Command::Open) {
+        let e = Error::new(ErrorKind::InvalidInput, "`info`, `archive` and `open` need a list file and can't be used with --stdin");
+        eprintln!("{}", describe_error(&e, None));
+        return exit_code(&e);
+    }
 
-            "rm" => {
-                match index.parse::<usize>() {
-                    Ok(parsed_index) => {
-                        remove_from_list(parsed_index);
-                        println!("Removed from your TODO list: {}", index);
-                    },
-                    Err(e) => {
-                        eprintln!("Error parsing index: {}", e);
-                    }
-                }
-            },
+    // The piped list is the only copy, so a change to it is lost unless `--stdout` prints it or `--dry-run` diffs it
+    // ^ stdin already holds the list, so `add -`, `import -` and `interactive` have nothing left to read from it
+    if args.stdin {
+        let reads_stdin = match &args.command {
+            Command::Add { messages, .. } => messages.iter().any(|message| message == "-"),
+            Command::Import { source, .. } => source.as_os_str() == "-",
+            Command::Interactive => true,
+            _ => false
+        };
+        let message = if reads_stdin {
+            Some("--stdin already reads the list from stdin, so `add -`, `import -` and `interactive` can't read from it too")
+        } else if changes_list(&args.command) && !args.stdout && !args.dry_run {
+            Some("--stdin has no file to write the changed list to, pass --stdout to print it or --dry-run to preview it")
+        } else {
+            None
+        };
+        if let Some(message) = message {
+            let e = Error::new(ErrorKind::InvalidInput, message);
+            eprintln!("{}", describe_error(&e, None));
+            return exit_code(&e);
+        }
+    }
 
-            "done" => {
-                match index.parse::<usize>() {
-                    Ok(parsed_index) => {
-                        mark_as_done(parsed_index, true);
-                        println!("Checked off item from your TODO list: {}", index);
-                    },
-                    Err(e) => {
-                        eprintln!("Error parsing index: {}", e);
-                    }
-                }
-            },
+    // CLI flags win over config.toml, which wins over the defaults
+    // ^ `-` only names the piped list in messages, `open_storage` never opens it
+    let path = match (args.file.as_deref(), args.list.as_deref()) {
+        _ if args.stdin => Ok(PathBuf::from("-")),
+        (Some(file), _) => Ok(expand_home(file)),
+        (None, Some(name)) => named_list_path(name),
+        (None, None) => Ok(TODO_PATH.clone())
+    };
+    let path = match path {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("{}", describe_error(&e, None));
+            return exit_code(&e);
+        }
+    };
+    // `info` reports which of these won, so it's handled here where `--file` and `--list` are still known
+    if let Command::Info = args.command {
+        let source = match (&args.file, &args.list) {
+            (Some(_), _) => "--file",
+            (None, Some(_)) => "--list",
+            (None, None) => todo_path_source()
+        };
+        print_info(&path, source);
+        return ExitCode::SUCCESS;
+    }
 
-            "undone" => {
-                match index.parse::<usize>() {
-                    Ok(parsed_index) => {
-                        mark_as_done(parsed_index, false);
-                        println!("Unchecked item from your TODO list: {}", index);
-                    },
-                    Err(e) => {
-                        eprintln!("Error parsing index: {}", e);
-                    }
-                }
-            },
+    // Only a list that may be written gets its directory, `info` above and previews leave the disk alone
+    let checked = match args.stdin {
+        true => check_piped_stdin(),
+        false if args.dry_run || args.stdout => check_list_path(&path),
+        false => check_list_path(&path).and_then(|_| create_list_dir(&path))
+    };
+    // The piped list has no path worth naming, `-` only stands in for it
+    let list_path = Some(path.as_path()).filter(|_| !args.stdin);
+    if let Err(e) = checked {
+        eprintln!("{}", describe_error(&e, list_path));
+        return exit_code(&e);
+    }
 
-            "list" => {
-                let list_content = get_list_content();
-                let parsed_list: String = parse_list_content(list_content);
-                
-                if parsed_list.is_empty() {
-                    println!("Nothing was found in your TODO list! 😊");
-                } else {
-                    println!("TODO list:\n{}", parsed_list);
-                }
-            },
+    // `always` and `never` are final, only `auto` looks at whether stdout is a terminal
+    let color = if args.force_color { Some(ColorChoice::Always) } else { args.color };
+    let color = color.or(CONFIG.color).unwrap_or(ColorChoice::Auto);
+    let options = Options {
+        color: color.enabled(),
+        quiet: args.quiet || args.stdout,
+        verbose: args.verbose,
+        dry_run: args.dry_run,
+        stdin: args.stdin,
+        stdout: args.stdout
+    };
+    set_done_marker(CONFIG.done_marker.clone());
+    let _ = SYMBOLS.set(if args.no_emoji || !emoji_supported() { &ASCII_SYMBOLS } else { &UNICODE_SYMBOLS });
 
-            "clear" => {
-                set_list_length(0);
-                println!("Your TODO list has been cleared!");
-            },
+    // A dry run changes an in-memory copy, whatever it ends up holding is diffed against the file afterwards
+    // ^ `--stdout` changes one the same way and prints it instead
+    let result = open_storage(&path, &options).and_then(|storage| {
+        let mut list = TodoList::new(storage);
+        let before = if options.dry_run { list.content()? } else { String::new() };
+
+        run(args.command, &mut list, &path, &options)?;
+
+        if options.dry_run {
+            print!("{}", diff_lines(&before, &list.content()?));
+            options.confirm("Dry run, your TODO list was left untouched.");
+        }
+        if options.stdout {
+            print!("{}", list.content()?);
+        }
+        Ok(list)
+    });
 
-            _ => println!("`{}` is not a valid command, run todo --help for more information.", command)
+    let list = match result {
+        Ok(list) => list,
+        Err(e) => {
+            if !is_no_matches(&e) {
+                eprintln!("{}", describe_error(&e, list_path));
+            }
+            return exit_code(&e);
+        }
+    };
+
+    // Stderr keeps this out of `--stdout`, `--json` and other output meant for another program
+    if options.verbose {
+        match list.list() {
+            Ok(items) => eprintln!("{} ({} items)", path.display(), items.len()),
+            Err(e) => eprintln!("{}", describe_error(&e, list_path))
         }
     }
+
+    ExitCode::SUCCESS
 }
 
-// Get the executable path
-fn inner_main() -> Result<PathBuf> {
-    let exe = current_exe()?;
-    let dir = exe.parent().expect("Executable must be in some directory").to_path_buf();
-    Ok(dir)
+// Whether a command can write the list, everything else only reads it or touches other files
+fn changes_list(command: &Command) -> bool {
+    !matches!(command,
+        Command::Export { .. } | Command::Search { .. } | Command::IndexOf { .. } | Command::Count { .. } | Command::PendingCount
+            | Command::Today { .. } | Command::Top { .. } | Command::Stats { .. } | Command::List(_) | Command::Info | Command::Lists
+            | Command::Use { .. } | Command::Completions { .. })
 }
 
-lazy_static! {
-    static ref TODO_PATH: PathBuf = {
-        let mut path = inner_main().expect("Failed to get executable path");
-        path.push("todo.txt");
-        path
-    };
+// Pick the exit code for an error, invalid input is a usage error and everything else came from IO
+fn exit_code(error: &Error) -> ExitCode {
+    match error.kind() {
+        _ if is_no_matches(error) => ExitCode::from(NO_MATCHES),
+        ErrorKind::InvalidInput => ExitCode::from(USAGE_ERROR),
+        _ => ExitCode::from(IO_ERROR)
+    }
+}
+
+// Returned by `search` when nothing matched, it has already said so and only sets the exit code
+#[derive(Debug)]
+struct NoMatches;
+
+impl Display for NoMatches {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "no items matched")
+    }
+}
+
+impl std::error::Error for NoMatches {}
+
+// Whether an error is `search` finding nothing rather than something going wrong
+fn is_no_matches(error: &Error) -> bool {
+    error.get_ref().is_some_and(|inner| inner.is::<NoMatches>())
 }
 
-// Clear list by setting the length of the file to 0
-fn set_list_length(size: u64) {
-    let file = get_file(false, true, false, false);
-    file.set_len(size).expect("Unable to clear file");
+// Flags for `list`, these only change what is printed and never the file
+#[derive(Args, Debug)]
+struct ListArgs {
+    /// Print the list as a JSON array instead
+    #[arg(long)]
+    json: bool,
+    /// Print only `[x] text` or `[ ] text` per item, without indices, colors or headers
+    #[arg(long, conflicts_with = "json")]
+    plain: bool,
+    /// Order the items are printed in, later keys in a list like `priority,due` break ties, the file itself is left untouched
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "index")]
+    sort: Vec<SortKey>,
+    /// Only show items that are checked off
+    #[arg(long, conflicts_with = "pending")]
+    done: bool,
+    /// Only show items that are not checked off
+    #[arg(long)]
+    pending: bool,
+    /// Only show items tagged with `+PROJECT`
+    #[arg(long)]
+    project: Option<String>,
+    /// Only show items tagged with `@CONTEXT`
+    #[arg(long)]
+    context: Option<String>,
+    /// Only show items due on or before this date, YYYY-MM-DD
+    #[arg(long, value_name = "DATE", value_parser = parse_date_arg)]
+    due_before: Option<Date>,
+    /// Only show items due on or after this date, YYYY-MM-DD
+    #[arg(long, value_name = "DATE", value_parser = parse_date_arg)]
+    due_after: Option<Date>,
+    /// Also print when each item was created and checked off
+    #[arg(long)]
+    show_dates: bool,
+    /// Print at most N items, after filtering and sorting
+    #[arg(long, value_name = "N", value_parser = parse_number_arg)]
+    limit: Option<usize>,
+    /// Reverse the printed order after sorting, showing the most recently added items first by default
+    #[arg(long, visible_alias = "reverse-sort")]
+    reverse: bool,
+    /// Print a key explaining the colors and markers after the list
+    #[arg(long)]
+    legend: bool,
+    /// Print each item's notes indented beneath it
+    #[arg(long)]
+    notes: bool,
+    /// Don't print the progress bar above the list
+    #[arg(long)]
+    no_progress: bool,
+    /// Don't print the item count summary below the list
+    #[arg(long)]
+    no_footer: bool,
+    /// Wrap long items to the terminal width, continuing under the text instead of the index
+    #[arg(long)]
+    wrap: bool,
+    /// Print the items under a header for each project, context or done state
+    #[arg(long, value_enum, value_name = "KEY")]
+    group_by: Option<GroupKey>,
+    /// Also set the terminal title to how many items are done, i.e. `todo: 3/10 done`, only when printing to a terminal
+    #[arg(long)]
+    set_title: bool,
+    /// Reset the terminal title set by `--set-title`
+    #[arg(long, conflicts_with = "set_title")]
+    reset_title: bool,
+    /// Print one short line per item marked `✓` or `·`, without the title, progress bar or footer
+    #[arg(long, conflicts_with_all = ["json", "plain", "header"])]
+    compact: bool,
+    /// Title printed above the items instead of `TODO list:`, defaults to `list_header` from config.toml, empty prints none
+    #[arg(long, value_name = "TEXT")]
+    header: Option<String>
 }
 
-// Read the list content as a string
-fn get_list_content() -> String {
-    let mut file = get_file(true, true, false, false);
-    let mut file_content = String::new();
-    
-    file.read_to_string(&mut file_content).unwrap();
+// Shells `completions` can generate a script for
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell
+}
 
-    file_content
+// A visible subcommand with everything a completion script needs to know about it
+struct CompletionEntry {
+    name: String,
+    about: String,
+    flags: Vec<String>
 }
 
-// Parse list content and apply formatting
-fn parse_list_content(content: String) -> String {
-    let mut result = String::new();
-    for line in content.lines() {
-        let mut parts = line.splitn(2, ' '); // Split on the first space
-        if let (Some(number), Some(rest)) = (parts.next(), parts.next()) {
-            let mut formatted_line = format!("{} {}", number, rest.trim_start());
-            if rest.ends_with("-s") {
-                formatted_line = format!("{} \x1b[9m{}\x1b[0m", number, rest.trim_end_matches("-s").trim_start().trim_end());
+// Introspect the derived parser for its subcommands and their long flags
+fn completion_entries() -> Vec<CompletionEntry> {
+    // Building propagates global flags like `--file` and the generated `--help` into every subcommand
+    let mut parser = CommandArguments::command();
+    parser.build();
+
+    parser.get_subcommands()
+        .filter(|subcommand| !subcommand.is_hide_set())
+        .map(|subcommand| {
+            let flags: Vec<String> = subcommand.get_arguments()
+                .filter_map(|arg| arg.get_long())
+                .map(|long| format!("--{}", long))
+                .collect();
+
+            CompletionEntry {
+                name: subcommand.get_name().to_string(),
+                about: subcommand.get_about().map_or(String::new(), |about| about.to_string()),
+                flags
             }
-            result.push_str(&formatted_line);
-            result.push('\n');
-        }
-    }
-    result
+        })
+        .collect()
 }
 
-// Find the next index for the list
-fn find_next_index(content: &str) -> usize {
-    let mut max_index = 0;
-    for line in content.lines() {
-        if let Some((index, _)) = line.split_once('.') {
-            if let Ok(num) = index.trim().parse::<usize>() {
-                if num > max_index {
-                    max_index = num;
+// Build a completion script for the given shell from the parser's subcommands
+fn generate_completions(shell: Shell) -> String {
+    let entries = completion_entries();
+    let names: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+    let mut script = String::new();
+
+    match shell {
+        Shell::Bash => {
+            script.push_str("_todo() {\n    local cur=\"${COMP_WORDS[COMP_CWORD]}\"\n");
+            script.push_str("    if [ \"$COMP_CWORD\" -eq 1 ]; then\n");
+            script.push_str(&format!("        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n", names.join(" ")));
+            script.push_str("        return\n    fi\n    case \"${COMP_WORDS[1]}\" in\n");
+            for entry in &entries {
+                script.push_str(&format!("        {}) COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")) ;;\n", entry.name, entry.flags.join(" ")));
+            }
+            script.push_str("    esac\n}\ncomplete -F _todo todo\n");
+        },
+
+        Shell::Zsh => {
+            script.push_str("#compdef todo\n\n_todo() {\n    local -a commands\n    commands=(\n");
+            for entry in &entries {
+                let about = entry.about.replace('\'', "'\\''").replace(':', "\\:");
+                script.push_str(&format!("        '{}:{}'\n", entry.name, about));
+            }
+            script.push_str("    )\n    if (( CURRENT == 2 )); then\n        _describe 'command' commands\n        return\n    fi\n");
+            script.push_str("    case \"${words[2]}\" in\n");
+            for entry in &entries {
+                script.push_str(&format!("        {}) compadd -- {} ;;\n", entry.name, entry.flags.join(" ")));
+            }
+            script.push_str("    esac\n}\n\n_todo \"$@\"\n");
+        },
+
+        Shell::Fish => {
+            script.push_str("complete -c todo -f\n");
+            for entry in &entries {
+                let about = entry.about.replace('\\', "\\\\").replace('\'', "\\'");
+                script.push_str(&format!("complete -c todo -n __fish_use_subcommand -a {} -d '{}'\n", entry.name, about));
+                for flag in &entry.flags {
+                    script.push_str(&format!("complete -c todo -n '__fish_seen_subcommand_from {}' -l {}\n", entry.name, flag.trim_start_matches("--")));
                 }
             }
+        },
+
+        Shell::Powershell => {
+            script.push_str("Register-ArgumentCompleter -Native -CommandName todo -ScriptBlock {\n");
+            script.push_str("    param($wordToComplete, $commandAst, $cursorPosition)\n");
+            script.push_str("    $commands = [ordered]@{\n");
+            for entry in &entries {
+                let flags: Vec<String> = entry.flags.iter().map(|flag| format!("'{}'", flag)).collect();
+                script.push_str(&format!("        '{}' = @({})\n", entry.name, flags.join(", ")));
+            }
+            script.push_str("    }\n    $words = @($commandAst.CommandElements | ForEach-Object { $_.ToString() })\n");
+            script.push_str("    $candidates = $commands.Keys\n");
+            script.push_str("    if ($words.Count -gt 2 -or ($words.Count -eq 2 -and -not $wordToComplete)) {\n");
+            script.push_str("        if ($commands.Contains($words[1])) { $candidates = $commands[$words[1]] }\n    }\n");
+            script.push_str("    $candidates | Where-Object { $_ -like \"$wordToComplete*\" } | ForEach-Object {\n");
+            script.push_str("        [System.Management.Automation.CompletionResult]::new($_, $_, 'ParameterValue', $_)\n    }\n}\n");
         }
     }
-    max_index + 1
+
+    script
+}
+
+// Orders `list` can print items in
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum SortKey {
+    /// File order
+    Index,
+    /// Highest priority first, unprioritized items last
+    Priority,
+    /// Earliest due date first, undated items last
+    Due,
+    /// Pending items before checked off ones
+    Done
 }
 
-// Append to the list by writing to it
-fn append_to_list(message: &str) {
-    let file_content = get_list_content();
-    let next_index = find_next_index(&file_content);
+// Tags `list --group-by` can cluster items by
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum GroupKey {
+    /// `+project` tags, items with several show up under each
+    Project,
+    /// `@context` tags, items with several show up under each
+    Context,
+    /// Pending and checked off
+    Done
+}
+
+// Formats `top` can print its item in
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum TopFormat {
+    /// The item as `list` prints it
+    Full,
+    /// Only the item's text
+    Text
+}
 
-    let formatted_message = format!("{}. {}\n", next_index, message);
+// Formats `import` can read items from
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum ImportFormat {
+    /// One item per line, with its metadata inline
+    Text,
+    /// A JSON array of `{text, done, priority, due}` objects
+    Json
+}
 
-    let mut file = get_file(true, true, false, false);
-    file.write_all(formatted_message.as_bytes()).expect("Unable to write to file");
+// Formats `export` can print items in
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ExportFormat {
+    /// `[x] text`
+    Plain,
+    /// GitHub task list, `- [x] text`
+    Markdown
 }
 
-// Remove a list item by index
-fn remove_from_list(index_number: usize) {
-    let file_content = get_list_content();
-    let mut new_content = String::new();
-    let mut item_found = false;
+// Dispatch the parsed command, propagating any file errors back to `main`
+fn run(command: Command, list: &mut TodoList, path: &Path, options: &Options) -> Result<()> {
+    match command {
+        Command::Add { messages, at, allow_duplicate } => {
+            // Unlike `import`, every line read here is part of the same item
+            let messages = messages.into_iter()
+                .map(|message| if message == "-" { read_to_string(stdin()) } else { Ok(message) })
+                .collect::<Result<Vec<String>>>()?;
+
+            let added = list.add(&messages, at, allow_duplicate)?;
+            for (message, index) in added.skipped {
+                eprintln!("Similar item already exists at index {}, skipped `{}` (use --allow-duplicate to add it anyway)", index, message);
+            }
+            for line in added.stored {
+                options.confirm(&format!("Added to your TODO list: {}", line));
+            }
+        },
+
+        Command::Rm { index: Some(index), .. } => {
+            let index = parse_index(&index, list)?;
+            list.remove(index)?;
+            options.confirm(&format!("Removed from your TODO list: {}", index));
+        },
 
-    for line in file_content.lines() {
-        if let Some((index, rest)) = line.split_once('.') {
-            if let Ok(num) = index.trim().parse::<usize>() {
-                if num == index_number {
-                    item_found = true;
-                } else {
-                    new_content.push_str(line);
-                    new_content.push('\n');
+        Command::Rm { index: None, project, matching, force } => {
+            let query = matching.as_deref().map(str::to_lowercase);
+            let is_target = |item: &TodoItem| match (&project, &query) {
+                (Some(project), _) => has_tag(&item.projects, project, '+'),
+                (None, Some(query)) => item.display_text().to_lowercase().contains(query.as_str()),
+                (None, None) => unreachable!("clap requires an index, --project or --matching")
+            };
+
+            let targets: Vec<TodoItem> = list.list()?.into_iter().filter(|item| is_target(item)).collect();
+            if targets.is_empty() {
+                options.confirm("No items matched, nothing was removed");
+                return Ok(());
+            }
+
+            // A dry run only previews, so there is nothing to confirm
+            if !force && !options.dry_run {
+                let lines: String = targets.iter().map(|item| format!("{}\n", format_item(item, options.color, false))).collect();
+                if !ask_to_confirm(&format!("{}Remove these {} items?", lines, targets.len()), "remove them")? {
+                    options.confirm("Your TODO list was left untouched.");
+                    return Ok(());
                 }
             }
-        } else {
-            new_content.push_str(line);
-            new_content.push('\n');
+
+            let removed = list.remove_where(is_target)?;
+            options.confirm(&format!("Removed {} items from your TODO list", removed.len()));
+        },
+
+        Command::Done { indices } => {
+            let indices = parse_indices(&indices, list)?;
+            let marked = list.set_done(&indices, true)?;
+            if !marked.checked.is_empty() {
+                options.confirm(&format!("Checked off item from your TODO list: {}", join_indices(&marked.checked)));
+            }
+            if !marked.unchanged.is_empty() {
+                options.confirm(&format!("Already checked off, nothing changed: {}", join_indices(&marked.unchanged)));
+            }
+            for item in &marked.recurring {
+                let due = item.due.expect("Recurring copies always get a due date");
+                options.confirm(&format!("Added the next occurrence as item {}, due on {}", item.index, due));
+            }
+
+            let missing = missing_indices(&indices, &marked);
+            if !missing.is_empty() {
+                return Err(not_found_error(&missing));
+            }
+        },
+
+        Command::Undone { indices } => {
+            let indices = parse_indices(&indices, list)?;
+            let marked = list.set_done(&indices, false)?;
+            if !marked.unchecked.is_empty() {
+                options.confirm(&format!("Unchecked item from your TODO list: {}", join_indices(&marked.unchecked)));
+            }
+            if !marked.unchanged.is_empty() {
+                options.confirm(&format!("Already pending, nothing changed: {}", join_indices(&marked.unchanged)));
+            }
+
+            let missing = missing_indices(&indices, &marked);
+            if !missing.is_empty() {
+                return Err(not_found_error(&missing));
+            }
+        },
+
+        Command::Toggle { indices } => {
+            let indices = parse_indices(&indices, list)?;
+            let marked = list.toggle(&indices)?;
+
+            // Reported in the order the indices were given, whichever way each one flipped
+            for index in indices.iter().filter_map(|index| marked.moved.get(index)) {
+                if marked.checked.contains(index) {
+                    options.confirm(&format!("{} now done", index));
+                } else if marked.unchecked.contains(index) {
+                    options.confirm(&format!("{} now pending", index));
+                }
+            }
+            for item in &marked.recurring {
+                let due = item.due.expect("Recurring copies always get a due date");
+                options.confirm(&format!("Added the next occurrence as item {}, due on {}", item.index, due));
+            }
+
+            let missing = missing_indices(&indices, &marked);
+            if !missing.is_empty() {
+                return Err(not_found_error(&missing));
+            }
+        },
+
+        Command::Swap { first, second } => {
+            let (first, second) = (parse_index(&first, list)?, parse_index(&second, list)?);
+            list.swap(first, second)?;
+            options.confirm(&format!("Swapped items {} and {}", first, second));
+        },
+
+        Command::RenameProject { old, new } => {
+            let renamed = list.rename_tag('+', &old, &new)?;
+            options.confirm(&format!("Renamed the project on {} items", renamed));
+        },
+
+        Command::RenameContext { old, new } => {
+            let renamed = list.rename_tag('@', &old, &new)?;
+            options.confirm(&format!("Renamed the context on {} items", renamed));
+        },
+
+        Command::Edit { index, message, append } => {
+            let index = parse_index(&index, list)?;
+            let item = match (message, append) {
+                (_, Some(text)) => list.append_text(index, &text)?,
+                (Some(message), None) => list.edit(index, &message)?,
+                (None, None) => unreachable!("clap requires a message or --append")
+            };
+            options.confirm(&format!("Edited item {} in your TODO list: {}", item.index, item.to_line()));
+        },
+
+        Command::Pri { index, priority } => {
+            let index = parse_index(&index, list)?;
+            match parse_priority(&priority)? {
+                Some(letter) => {
+                    let index = list.set_priority(index, Some(letter))?;
+                    options.confirm(&format!("Set priority of item {} to ({})", index, letter));
+                },
+                None => {
+                    let index = list.set_priority(index, None)?;
+                    options.confirm(&format!("Cleared priority of item {}", index));
+                }
+            }
+        },
+
+        Command::Due { index, date } => {
+            let index = parse_index(&index, list)?;
+            match parse_due_date(&date.join(" "))? {
+                Some(due) => {
+                    let index = list.set_due_date(index, Some(due))?;
+                    options.confirm(&format!("Item {} is now due on {}", index, due));
+                },
+                None => {
+                    let index = list.set_due_date(index, None)?;
+                    options.confirm(&format!("Cleared due date of item {}", index));
+                }
+            }
+        },
+
+        Command::Label { index, label } => {
+            let index = parse_index(&index, list)?;
+            let color = label.trim().to_lowercase();
+            if color == "none" {
+                let index = list.set_label(index, None)?;
+                options.confirm(&format!("Cleared the label of item {}", index));
+            } else {
+                let index = list.set_label(index, Some(&color))?;
+                options.confirm(&format!("Labeled item {} {}", index, color));
+            }
+        },
+
+        Command::Note { index, note } => {
+            let index = parse_index(&index, list)?;
+            let index = list.add_note(index, &note)?;
+            options.confirm(&format!("Added a note to item {}", index));
+        },
+
+        Command::Import { source, format } => {
+            let imported = import_items(list, &source, format)?;
+            options.confirm(&format!("Imported {} items", imported));
+        },
+
+        Command::Export { format } => {
+            for item in list.list()? {
+                println!("{}", export_item(&item, format));
+            }
+        },
+
+        Command::Search { query, done, pending } => {
+            let matches: Vec<TodoItem> = list.search(&query)?
+                .into_iter()
+                .filter(|item| (!done || item.done) && (!pending || !item.done))
+                .collect();
+
+            // Like grep, finding nothing is reported through the exit code so scripts can test for it
+            if matches.is_empty() {
+                if !options.quiet {
+                    eprintln!("No items matched `{}`", query);
+                }
+                return Err(Error::other(NoMatches));
+            }
+
+            for item in &matches {
+                println!("{}", format_item(item, options.color, false));
+            }
+        },
+
+        Command::IndexOf { text } => {
+            let indices = list.index_of(&text)?;
+            if indices.is_empty() {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("No item has the text `{}`", text.trim())));
+            }
+
+            for index in indices {
+                println!("{}", index);
+            }
+        },
+
+        Command::Count { pending } => {
+            let (total, done_count) = list.count()?;
+            let pending_count = total - done_count;
+
+            if pending {
+                println!("{}", pending_count);
+            } else {
+                println!("{} total, {} pending, {} done", total, pending_count, done_count);
+            }
+        },
+
+        // Meant for shell prompts, so an empty output lets the prompt hide the segment
+        Command::PendingCount => {
+            let (total, done_count) = list.count()?;
+            let pending_count = total - done_count;
+            if pending_count > 0 {
+                print!("{}", pending_count);
+                stdout().flush()?;
+            }
+        },
+
+        Command::Top { format } => match next_item(list.list()?) {
+            Some(item) => match format {
+                TopFormat::Full => println!("{}", format_item(&item, options.color, false)),
+                TopFormat::Text => println!("{}", item.display_text())
+            },
+            None => println!("All done!")
+        },
+
+        Command::Today { include_undated } => {
+            let items = due_today(list.list()?, include_undated);
+            print_list(&items, &RenderOptions { color: options.color, ..RenderOptions::default() }, 0);
+        },
+
+        Command::Stats { since, until } => print_stats(&list.list()?, since, until),
+
+        Command::List(args) => list_items(list, &args, options)?,
+
+        Command::Interactive => interactive_mode(list, options)?,
+
+        Command::Purge { older_than } => {
+            let purged = list.purge(older_than.map(completed_cutoff))?;
+            options.confirm(&format!("Purged {} items from your TODO list", purged.len()));
+        },
+
+        Command::Archive { older_than } => {
+            let archived = list.archive(&mut open_storage(&archive_path(path), options)?, older_than.map(completed_cutoff))?;
+            if archived == 0 && older_than.is_some() {
+                options.confirm("Nothing to archive, no items were checked off that long ago");
+            } else if archived == 0 {
+                options.confirm("Nothing to archive, no items are checked off");
+            } else {
+                options.confirm(&format!("Archived {} items to {}", archived, archive_path(path).display()));
+            }
+        },
+
+        Command::Clear { force, done: true } => {
+            let (_, done_count) = list.count()?;
+            if done_count == 0 {
+                options.confirm("Nothing to clear, no items are checked off");
+                return Ok(());
+            }
+
+            // A dry run only previews, so there is nothing to confirm
+            if !force && !options.dry_run && !ask_to_confirm(&format!("Remove {} done items?", done_count), "clear")? {
+                options.confirm("Your TODO list was left untouched.");
+                return Ok(());
+            }
+
+            let purged = list.purge(None)?;
+            options.confirm(&format!("Removed {} done items from your TODO list", purged.len()));
+        },
+
+        Command::Clear { force, done: false } => {
+            let (item_count, _) = list.count()?;
+
+            if !force && !options.dry_run && item_count > 0 && !ask_to_confirm(&format!("Clear all {} items?", item_count), "clear")? {
+                options.confirm("Your TODO list was left untouched.");
+                return Ok(());
+            }
+
+            list.clear()?;
+            options.confirm("Your TODO list has been cleared!");
+        },
+
+        Command::Undo => {
+            list.undo()?;
+            options.confirm("Reverted the last change to your TODO list");
+        },
+
+        Command::Open => {
+            if options.dry_run || options.stdout {
+                return Err(Error::new(ErrorKind::InvalidInput, "`open` edits the file itself and can't be used with --dry-run or --stdout"));
+            }
+
+            // Reading first creates the file, so the editor never starts on a path that doesn't exist
+            list.content()?;
+            open_in_editor(path)?;
+
+            if list.renumber()? {
+                options.confirm("Renumbered the items in your TODO list");
+            }
+            for (number, line) in malformed_lines(&list.content()?) {
+                eprintln!("Line {} of {} isn't an item, note or comment and won't be shown: `{}`", number, path.display(), line);
+            }
+        },
+
+        Command::Migrate => {
+            let migrated = list.migrate()?;
+            if migrated == 0 {
+                options.confirm("Nothing to migrate, every item is already in the current format");
+            } else {
+                options.confirm(&format!("Rewrote {} items in the current format", migrated));
+            }
+        },
+
+        Command::Info => unreachable!("`main` prints info before opening the list, it needs to know which flag chose the path"),
+
+        Command::Lists => print_lists(path)?,
+
+        Command::Use { name } => {
+            let list_path = named_list_path(&name)?;
+            if !options.dry_run {
+                set_config_value("list", &name)?;
+            }
+            options.confirm(&format!("Now using the `{}` list at {}", name, list_path.display()));
+        },
+
+        Command::Completions { .. } => unreachable!("`main` prints completions before resolving the list path")
+    }
+
+    Ok(())
+}
+
+// Ask on stdin before removing items, refusing outright when there is no one to ask
+// ^ `action` finishes the refusal, i.e. "pass --force to clear anyway"
+fn ask_to_confirm(question: &str, action: &str) -> Result<bool> {
+    if !stdin().is_terminal() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Refusing to {} without confirmation since stdin is not a terminal, pass --force to {} anyway", action, action)
+        ));
+    }
+
+    print!("{} [y/N] ", question);
+    stdout().flush()?;
+
+    let mut answer = String::new();
+    stdin().read_line(&mut answer)?;
+
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+// Turn an IO error into a short, user facing message naming the list at `path`, if it's known yet
+fn describe_error(error: &Error, path: Option<&Path>) -> String {
+    let list = path.map_or("your TODO list".to_string(), |path| path.display().to_string());
+    match error.kind() {
+        ErrorKind::PermissionDenied => format!("Cannot write to {}: permission denied", list),
+        ErrorKind::NotFound if error.get_ref().is_none() => format!("Cannot open {}: path not found", list),
+        ErrorKind::InvalidInput => error.to_string(),
+        // `check_list_path` and encoding errors from reading the list already name the file
+        ErrorKind::NotFound | ErrorKind::IsADirectory => format!("Cannot open the list: {}", error),
+        ErrorKind::InvalidData if error.get_ref().is_some() => error.to_string(),
+        _ => format!("Cannot access {}: {}", list, error)
+    }
+}
+
+// Get the directory of the executable, or the current directory with a warning if it can't be found
+// ^ `current_exe` can fail or return a bare path, i.e. when the binary was deleted while it runs
+fn inner_main() -> Result<PathBuf> {
+    let dir = current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf)).filter(|dir| !dir.as_os_str().is_empty());
+    match dir {
+        Some(dir) => Ok(dir),
+        None => {
+            let cwd = current_dir()?;
+            eprintln!("Cannot find the directory todo-rs runs from, keeping todo.txt in {} instead", cwd.display());
+            Ok(cwd)
         }
     }
+}
 
-    if !item_found {
-        eprintln!("Item with index {} not found.", index_number);
-        return;
+// Get the platform specific data directory, i.e. `$XDG_DATA_HOME` on Linux
+fn data_dir() -> Option<PathBuf> {
+    if cfg!(target_os = "windows") {
+        return var_os("APPDATA").map(PathBuf::from);
     }
 
-    let mut file = get_file(true, true, false, true);
-    file.set_len(0).expect("Unable to clear file");
-    file.write_all(new_content.as_bytes()).expect("Unable to write to file");
-}
-
-// Mark an item as done by appending -s to the end
-fn mark_as_done(index_number: usize, done: bool) {
-    let file_content = get_list_content();
-    let mut new_content = String::new();
-    let mut item_found = false;
-
-    for line in file_content.lines() {
-        if let Some((index, rest)) = line.split_once('.') {
-            if let Ok(num) = index.trim().parse::<usize>() {
-                if num == index_number {
-                    let trimmed_rest = rest.trim_start();
-                    let updated_line;
-
-                    if done {
-                        if trimmed_rest.ends_with(" -s") {
-                            updated_line = format!("{}. {}\n", index_number, trimmed_rest);
-                        } else {
-                            updated_line = format!("{}. {} -s\n", index_number, trimmed_rest);
-                        }
-                    } else {
-                        if trimmed_rest.ends_with(" -s") {
-                            updated_line = format!("{}. {}\n", index_number, trimmed_rest.trim_end_matches(" -s"));
-                        } else {
-                            updated_line = format!("{}. {}\n", index_number, trimmed_rest);
-                        }
-                    }
+    let home = var_os("HOME").map(PathBuf::from);
 
-                    new_content.push_str(&updated_line);
-                    item_found = true;
-                } else {
-                    new_content.push_str(line);
-                    new_content.push('\n');
-                }
-            }
-        } else {
-            new_content.push_str(line);
-            new_content.push('\n');
+    if cfg!(target_os = "macos") {
+        return home.map(|home| home.join("Library").join("Application Support"));
+    }
+
+    match var_os("XDG_DATA_HOME") {
+        Some(xdg) if !xdg.is_empty() => Some(PathBuf::from(xdg)),
+        _ => home.map(|home| home.join(".local").join("share"))
+    }
+}
+
+// Get the platform specific config directory, i.e. `~/.config` on Linux and macOS
+fn config_dir() -> Option<PathBuf> {
+    if cfg!(target_os = "windows") {
+        return var_os("APPDATA").map(PathBuf::from);
+    }
+
+    match var_os("XDG_CONFIG_HOME") {
+        Some(xdg) if !xdg.is_empty() => Some(PathBuf::from(xdg)),
+        _ => var_os("HOME").map(|home| PathBuf::from(home).join(".config"))
+    }
+}
+
+// Settings read from `todo-rs/config.toml` in the config directory, every key is optional
+//
+//     path = "~/notes/todo.txt"
+//     color = "never"
+//     done_marker = "-s"
+//     list = "work"
+//     list_header = "Team tasks"
+#[derive(Debug)]
+struct TodoConfig {
+    // Where the `default` list lives
+    path: Option<PathBuf>,
+    // The active named list, set by `todo use`
+    list: Option<String>,
+    color: Option<ColorChoice>,
+    // `[x]` writes a checkbox in front of done items, anything else is a suffix after them
+    done_marker: DoneMarker,
+    // Title `list` prints above the items, an empty one prints no title line
+    list_header: Option<String>
+}
+
+impl Default for TodoConfig {
+    fn default() -> Self {
+        TodoConfig { path: None, list: None, color: None, done_marker: DoneMarker::Checkbox, list_header: None }
+    }
+}
+
+// Get the path config.toml is read from, if there is a config directory at all
+fn config_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("todo-rs").join("config.toml"))
+}
+
+// Load config.toml, silently falling back to the defaults when it doesn't exist
+fn load_config() -> TodoConfig {
+    let Some(path) = config_path() else {
+        return TodoConfig::default();
+    };
+
+    match fs::read_to_string(&path) {
+        Ok(content) => parse_config(&content, &path),
+        Err(e) if e.kind() == ErrorKind::NotFound => TodoConfig::default(),
+        Err(e) => {
+            eprintln!("Ignoring {}: {}", path.display(), e);
+            TodoConfig::default()
         }
     }
+}
+
+// Parse the `key = "value"` subset of TOML the config uses, warning about anything else
+fn parse_config(content: &str, path: &Path) -> TodoConfig {
+    let mut config = TodoConfig::default();
+
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let parsed = line.split_once('=').and_then(|(key, value)| Some((key.trim(), parse_toml_string(value.trim())?)));
+        let applied = match parsed {
+            Some(("path", value)) => {
+                config.path = Some(expand_home(Path::new(&value)));
+                true
+            },
+            Some(("color", value)) => match ColorChoice::from_str(&value, true) {
+                Ok(color) => {
+                    config.color = Some(color);
+                    true
+                },
+                Err(_) => false
+            },
+            Some(("list", value)) if valid_list_name(&value) => {
+                config.list = Some(value);
+                true
+            },
+            Some(("done_marker", value)) if value.trim() == "[x]" => {
+                config.done_marker = DoneMarker::Checkbox;
+                true
+            },
+            Some(("done_marker", value)) if !value.trim().is_empty() => {
+                config.done_marker = DoneMarker::Suffix(format!(" {}", value.trim()));
+                true
+            },
+            Some(("list_header", value)) => {
+                config.list_header = Some(value);
+                true
+            },
+            _ => false
+        };
 
-    if !item_found {
-        eprintln!("Item with index {} not found.", index_number);
+        if !applied {
+            eprintln!("Ignoring line {} of {}: `{}`", number + 1, path.display(), line);
+        }
     }
 
-    let mut file = get_file(true, true, false, true);
-    file.set_len(0).expect("Unable to clear file"); // Clear the file content
-    file.write_all(new_content.as_bytes()).expect("Unable to write to file");
+    config
 }
 
-// Get the file using OpenOptions with required parameters regarding the permissions
-// ^ In every command used, if todo.txt doesnt exist it will create it for them.
-fn get_file(read: bool, write: bool, append: bool, truncate: bool) -> File {
-    if write == false && append == false {
-        panic!("Either `append` or `write` must be true in `get_file`")
+// Parse a basic or literal TOML string, i.e. `"value"` or `'value'`, ignoring a trailing comment
+fn parse_toml_string(value: &str) -> Option<String> {
+    if let Some(rest) = value.strip_prefix('\'') {
+        let (literal, after) = rest.split_once('\'')?;
+        return toml_trailing_ok(after).then(|| literal.to_string());
+    }
+
+    let mut chars = value.strip_prefix('"')?.chars();
+    let mut parsed = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return toml_trailing_ok(chars.as_str()).then_some(parsed),
+            '\\' => match chars.next()? {
+                'n' => parsed.push('\n'),
+                't' => parsed.push('\t'),
+                escaped @ ('"' | '\\') => parsed.push(escaped),
+                _ => return None
+            },
+            c => parsed.push(c)
+        }
     }
+    None
+}
+
+// Only whitespace or a comment may follow a value
+fn toml_trailing_ok(rest: &str) -> bool {
+    let rest = rest.trim();
+    rest.is_empty() || rest.starts_with('#')
+}
 
-    let file = OpenOptions::new()
-        .read(read)
-        .create(true)
-        .write(write)
-        .append(append)
-        .truncate(truncate)
-        .open(&*TODO_PATH)
-        .expect("Unable to open or create file");
+// Expand a leading `~` to the home directory, since not every shell or config does it for us
+fn expand_home(path: &Path) -> PathBuf {
+    let home = if cfg!(target_os = "windows") { var_os("USERPROFILE") } else { var_os("HOME") };
 
-    file
-}
\ No newline at end of file
+    match (path.strip_prefix("~"), home) {
+        (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => path.to_path_buf()
+    }
+}
+
+// Create the directory a list file lives in if it is missing, the first read then creates the file itself
+fn create_list_dir(path: &Path) -> Result<()> {
+    match path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        Some(parent) => create_dir_all(parent),
+        None => Ok(())
+    }
+}
+
+// Reject a resolved list path that can never be opened as a file, before any command tries to
+// ^ A path that doesn't exist yet is fine, the first read creates it
+fn check_list_path(path: &Path) -> Result<()> {
+    let Ok(link) = fs::symlink_metadata(path) else {
+        return Ok(());
+    };
+
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.is_dir() => Err(Error::new(
+            ErrorKind::IsADirectory,
+            format!("{} exists but is a directory, point --file or TODO_RS_PATH at a file instead", path.display())
+        )),
+        Err(e) if e.kind() == ErrorKind::NotFound && link.is_symlink() => {
+            let target = fs::read_link(path)?;
+            Err(Error::new(
+                ErrorKind::NotFound,
+                format!("{} is a symlink to {}, which doesn't exist", path.display(), target.display())
+            ))
+        },
+        Err(e) => Err(e),
+        Ok(_) => Ok(())
+    }
+}
+
+// `--stdin` would otherwise sit waiting for a list to be typed in
+fn check_piped_stdin() -> Result<()> {
+    if stdin().is_terminal() {
+        return Err(Error::new(ErrorKind::InvalidInput, "--stdin reads the list from a pipe, i.e. `cat todo.txt | todo --stdin list`"));
+    }
+    Ok(())
+}
+
+// Resolve where todo.txt lives, `main` creates its directory once it knows the list will be written
+// ^ `TODO_RS_PATH` overrides the location entirely, i.e. `TODO_RS_PATH=./todo.txt`, then the active list from
+//   `todo use` and finally the `default` list
+fn resolve_todo_path() -> Result<PathBuf> {
+    if let Some(path) = var_os("TODO_RS_PATH") {
+        return Ok(PathBuf::from(path));
+    }
+
+    match &CONFIG.list {
+        Some(name) => named_list_path(name),
+        None => default_list_path()
+    }
+}
+
+// Name the setting `resolve_todo_path` took the list path from, for `info`
+fn todo_path_source() -> &'static str {
+    if var_os("TODO_RS_PATH").is_some() {
+        "TODO_RS_PATH"
+    } else if CONFIG.list.as_deref().is_some_and(|name| name != DEFAULT_LIST) {
+        "list in config.toml"
+    } else if CONFIG.path.is_some() {
+        "path in config.toml"
+    } else {
+        "default data directory"
+    }
+}
+
+// The name of the list kept at `path` from config.toml or todo.txt in the data directory
+const DEFAULT_LIST: &str = "default";
+
+// Get the path of the `default` list
+fn default_list_path() -> Result<PathBuf> {
+    if let Some(path) = &CONFIG.path {
+        return Ok(path.clone());
+    }
+
+    Ok(app_data_dir()?.join("todo.txt"))
+}
+
+// Get the `todo-rs` data directory, or the executable's directory on platforms without one
+fn app_data_dir() -> Result<PathBuf> {
+    match data_dir() {
+        Some(dir) => Ok(dir.join("todo-rs")),
+        None => inner_main()
+    }
+}
+
+// Get the path of a named list, i.e. `lists/work.txt` in the data directory
+fn named_list_path(name: &str) -> Result<PathBuf> {
+    if !valid_list_name(name) {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("`{}` is not a valid list name, use letters, digits, `-` and `_`", name)));
+    }
+
+    if name == DEFAULT_LIST {
+        return default_list_path();
+    }
+
+    Ok(app_data_dir()?.join("lists").join(format!("{}.txt", name)))
+}
+
+// List names become file names, so they're kept to characters that are safe on every platform
+fn valid_list_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+// Print the `default` list and every list in the `lists` directory, marking the one at `path` with `*`
+fn print_lists(path: &Path) -> Result<()> {
+    let mut names = Vec::new();
+    match fs::read_dir(app_data_dir()?.join("lists")) {
+        Ok(entries) => {
+            for entry in entries {
+                let file = entry?.path();
+                let name = file.file_stem().and_then(|stem| stem.to_str()).filter(|name| valid_list_name(name));
+                if let (Some(name), Some("txt")) = (name, file.extension().and_then(|extension| extension.to_str())) {
+                    if name != DEFAULT_LIST {
+                        names.push(name.to_string());
+                    }
+                }
+            }
+        },
+        Err(e) if e.kind() == ErrorKind::NotFound => {},
+        Err(e) => return Err(e)
+    }
+    names.sort();
+    names.insert(0, DEFAULT_LIST.to_string());
+
+    for name in names {
+        let active = named_list_path(&name)? == path;
+        println!("{} {}", if active { "*" } else { " " }, name);
+    }
+
+    Ok(())
+}
+
+// Set `key = "value"` in config.toml, replacing the first line setting that key or appending one
+// ^ Every other line, comments included, is written back as it was
+fn set_config_value(key: &str, value: &str) -> Result<()> {
+    let Some(path) = config_path() else {
+        return Err(Error::new(ErrorKind::NotFound, "There is no config directory to save the setting in"));
+    };
+
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e)
+    };
+
+    let setting = format!("{} = \"{}\"", key, value);
+    let mut replaced = false;
+    let mut lines: Vec<String> = content.lines()
+        .map(|line| {
+            let sets_key = line.split_once('=').is_some_and(|(existing, _)| existing.trim() == key);
+            if sets_key && !replaced {
+                replaced = true;
+                setting.clone()
+            } else {
+                line.to_string()
+            }
+        })
+        .collect();
+
+    if !replaced {
+        lines.push(setting);
+    }
+
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent)?;
+    }
+    fs::write(&path, lines.join("\n") + "\n")
+}
+
+// Print the resolved list and config paths, never creating either file
+fn print_info(path: &Path, source: &str) {
+    let absolute = absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    println!("List:   {}", absolute.display());
+    println!("Source: {}", source);
+
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.is_file() => {
+            let items = fs::read_to_string(path).map_or(0, |content| parse_items(&content).len());
+            println!("Status: {} bytes, {} items", metadata.len(), items);
+        },
+        Ok(_) => println!("Status: exists but is not a file"),
+        Err(e) if e.kind() == ErrorKind::NotFound => println!("Status: not created yet"),
+        Err(e) => println!("Status: {}", e)
+    }
+
+    match config_path() {
+        Some(config) if config.is_file() => println!("Config: {}", config.display()),
+        Some(config) => println!("Config: {} (not found, using defaults)", config.display()),
+        None => println!("Config: no config directory, using defaults")
+    }
+}
+
+// Every emoji and symbol printed to the terminal, so `--no-emoji` can swap them all at once
+struct Symbols {
+    // Printed instead of the list when nothing is in it or nothing matched
+    empty_list: &'static str,
+    // Marks overdue items and explains them in the legend
+    overdue: &'static str,
+    // The filled and empty cells of the progress bar
+    bar_done: &'static str,
+    bar_pending: &'static str,
+    // Mark done and pending items in `list --compact`
+    compact_done: &'static str,
+    compact_pending: &'static str
+}
+
+const UNICODE_SYMBOLS: Symbols = Symbols {
+    empty_list: "Nothing was found in your TODO list! 😊",
+    overdue: "⚠",
+    bar_done: "■",
+    bar_pending: "□",
+    compact_done: "✓",
+    compact_pending: "·"
+};
+
+const ASCII_SYMBOLS: Symbols = Symbols {
+    empty_list: "Nothing in your TODO list!",
+    overdue: "!",
+    bar_done: "#",
+    bar_pending: "-",
+    compact_done: "x",
+    compact_pending: "o"
+};
+
+// Set once in `main` from `--no-emoji` and the environment
+static SYMBOLS: OnceLock<&'static Symbols> = OnceLock::new();
+
+fn symbols() -> &'static Symbols {
+    SYMBOLS.get().copied().unwrap_or(&UNICODE_SYMBOLS)
+}
+
+// Guess whether the terminal can show emoji, `NO_EMOJI` says no outright and otherwise the locale has to be UTF-8
+// ^ Without any locale variable, i.e. on Windows, emoji are assumed to work
+fn emoji_supported() -> bool {
+    if var_os("NO_EMOJI").is_some_and(|value| !value.is_empty()) {
+        return false;
+    }
+
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"].iter()
+        .filter_map(var_os)
+        .find(|value| !value.is_empty());
+
+    match locale {
+        Some(locale) => {
+            let locale = locale.to_string_lossy().to_lowercase();
+            locale.contains("utf-8") || locale.contains("utf8")
+        },
+        None => true
+    }
+}
+
+lazy_static! {
+    static ref CONFIG: TodoConfig = load_config();
+
+    static ref TODO_PATH: PathBuf = resolve_todo_path().unwrap_or_else(|e| {
+        eprintln!("{}", describe_error(&e, None));
+        exit(IO_ERROR.into());
+    });
+}
+
+// Open a list file, or with `--dry-run` or `--stdout` an in-memory copy of it that is thrown away afterwards
+// ^ The copy carries the backup too so `undo` can be previewed, a list that doesn't exist yet reads as empty
+// ^ With `--stdin` the list read from the pipe is the only copy, there's no file behind it
+fn open_storage(path: &Path, options: &Options) -> Result<Box<dyn Storage>> {
+    if options.stdin {
+        return Ok(Box::new(InMemoryStorage::new(read_to_string(stdin())?)));
+    }
+
+    let file = FileStorage::new(path);
+    if !options.dry_run && !options.stdout {
+        return Ok(Box::new(file));
+    }
+
+    let content = if path.exists() { file.read()? } else { String::new() };
+    let backup = fs::read_to_string(file.backup_path()).ok();
+    Ok(Box::new(InMemoryStorage::with_backup(content, backup)))
+}
+
+// Diff two versions of the list line by line, prefixing removed lines with `-` and added ones with `+`
+// ^ Unchanged lines are left out, the longest common subsequence decides which lines count as unchanged
+fn diff_lines(old_content: &str, new_content: &str) -> String {
+    let old: Vec<&str> = old_content.lines().collect();
+    let new: Vec<&str> = new_content.lines().collect();
+
+    // common[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut common = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && common[i + 1][j] >= common[i][j + 1]) {
+            diff.push_str(&format!("-{}\n", old[i]));
+            i += 1;
+        } else {
+            diff.push_str(&format!("+{}\n", new[j]));
+            j += 1;
+        }
+    }
+
+    diff
+}
+
+// Color `+project` and `@context` tokens, only resetting the foreground so strikethrough survives
+fn color_tags(text: &str) -> String {
+    text.split(' ')
+        .map(|word| match word.chars().next() {
+            Some('+') if word.len() > 1 => format!("\x1b[36m{}\x1b[39m", word),
+            Some('@') if word.len() > 1 => format!("\x1b[35m{}\x1b[39m", word),
+            _ => word.to_string()
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+// Get the ANSI foreground code for a label color
+fn label_code(label: &str) -> u8 {
+    match label {
+        "red" => 31,
+        "green" => 32,
+        "yellow" => 33,
+        "blue" => 34,
+        "magenta" => 35,
+        "cyan" => 36,
+        _ => 39
+    }
+}
+
+// Sort items in place by each key in turn, a later key only decides between items every earlier key ties
+// ^ Ties left after the last key keep their file order since the sort is stable
+fn sort_items(items: &mut [TodoItem], keys: &[SortKey]) {
+    items.sort_by(|a, b| {
+        keys.iter()
+            .map(|key| match key {
+                SortKey::Index => a.index.cmp(&b.index),
+                SortKey::Priority => a.priority.unwrap_or(char::MAX).cmp(&b.priority.unwrap_or(char::MAX)),
+                SortKey::Due => a.due.map_or(i64::MAX, Date::to_days).cmp(&b.due.map_or(i64::MAX, Date::to_days)),
+                SortKey::Done => a.done.cmp(&b.done)
+            })
+            .find(|order| order.is_ne())
+            .unwrap_or(Ordering::Equal)
+    });
+}
+
+// Format an item the same way `list` does, done items are struck through and dimmed,
+// priority A and B items are bold red and yellow, and overdue items get a ⚠ prefix
+// ^ Without color, done items get a `[x]` prefix instead and priorities are only shown as `(A)`
+fn format_item(item: &TodoItem, color: bool, show_dates: bool) -> String {
+    let overdue = item.is_overdue(Date::today());
+    let high_priority = !item.done && matches!(item.priority, Some('A' | 'B'));
+
+    // Tag colors would reset the priority or label color halfway through the line, so they're skipped for those
+    let text = if color && !high_priority && item.label.is_none() { unescape_text(&color_tags(&item.text)) } else { item.display_text() };
+    let text = match item.priority {
+        Some(priority) => format!("({}) {}", priority, text),
+        None => text
+    };
+
+    let text = match (color, item.done, item.priority) {
+        (true, true, _) => format!("\x1b[9;2m{}\x1b[0m", text),
+        (true, false, Some('A')) => format!("\x1b[1;31m{}\x1b[0m", text),
+        (true, false, Some('B')) => format!("\x1b[1;33m{}\x1b[0m", text),
+        (true, false, _) => match &item.label {
+            Some(label) => format!("\x1b[{}m{}\x1b[0m", label_code(label), text),
+            None => text
+        },
+        (false, true, _) => format!("[x] {}", text),
+        _ => text
+    };
+
+    let mut line = if overdue {
+        format!("{}. {} {}", item.index, symbols().overdue, text)
+    } else {
+        format!("{}. {}", item.index, text)
+    };
+
+    // Overdue pending items are highlighted in red, every other due date is dimmed
+    // ^ Pending items also get how far off their due date is, done ones only keep the date
+    if let Some(due) = item.due {
+        let due = match item.done {
+            true => format!("due:{}", due),
+            false => format!("due:{} ({})", due, due_status(due, Date::today()))
+        };
+
+        if !color {
+            line.push_str(&format!(" {}", due));
+        } else if overdue {
+            line.push_str(&format!(" \x1b[31m{}\x1b[0m", due));
+        } else {
+            line.push_str(&format!(" \x1b[2m{}\x1b[0m", due));
+        }
+    }
+
+    if show_dates {
+        let dates = [("created", item.created), ("done", item.completed)];
+        for (key, date) in dates.iter().filter_map(|(key, date)| date.map(|date| (key, date))) {
+            if color {
+                line.push_str(&format!(" \x1b[2m{}:{}\x1b[0m", key, date));
+            } else {
+                line.push_str(&format!(" {}:{}", key, date));
+            }
+        }
+    }
+
+    line
+}
+
+// Pick the pending item to do next, the highest priority first, then the earliest due date, then the lowest index
+// ^ Items without a priority or due date rank after every item that has one
+fn next_item(items: Vec<TodoItem>) -> Option<TodoItem> {
+    items.into_iter()
+        .filter(|item| !item.done)
+        .min_by_key(|item| (item.priority.unwrap_or(char::MAX), item.due.map_or(i64::MAX, Date::to_days), item.index))
+}
+
+// Describe how far off a due date is, i.e. `due in 3d`, `due today` or `overdue 2d`
+fn due_status(due: Date, today: Date) -> String {
+    let days = due.to_days() - today.to_days();
+    match days {
+        0 => "due today".to_string(),
+        days if days > 0 => format!("due in {}d", days),
+        days => format!("overdue {}d", -days)
+    }
+}
+
+// Keep the pending items due today or earlier, oldest due date first so overdue items lead
+// ^ Undated items are only kept with `include_undated`, they sort after every dated one
+fn due_today(items: Vec<TodoItem>, include_undated: bool) -> Vec<TodoItem> {
+    let today = Date::today();
+    let mut items: Vec<TodoItem> = items.into_iter()
+        .filter(|item| !item.done)
+        .filter(|item| item.due.map_or(include_undated, |due| due <= today))
+        .collect();
+
+    items.sort_by_key(|item| (item.due.is_none(), item.due));
+    items
+}
+
+// Print the key for the styles `list` uses, shown in those same styles when color is on
+fn print_legend(color: bool) {
+    if color {
+        println!("Legend: \x1b[9;2mdone\x1b[0m, \x1b[1;31m(A) priority\x1b[0m, \x1b[1;33m(B) priority\x1b[0m, {} overdue, \x1b[36m+project\x1b[39m, \x1b[35m@context\x1b[39m", symbols().overdue);
+    } else {
+        println!("Legend: [x] done, (A) priority, {} overdue, +project, @context", symbols().overdue);
+    }
+}
+
+// Print totals and the completion rate, plus overdue and completed today counts when items have dates
+// ^ With `since` or `until` the items checked off in that range are counted too, by their `done:` date
+//   Totals always cover the whole list, done items without a completion date just never fall in the range
+fn print_stats(items: &[TodoItem], since: Option<Date>, until: Option<Date>) {
+    if items.is_empty() {
+        println!("{}", symbols().empty_list);
+        return;
+    }
+
+    let today = Date::today();
+    let done_count = items.iter().filter(|item| item.done).count();
+    let completion = done_count as f64 / items.len() as f64 * 100.0;
+
+    println!("Total: {}", items.len());
+    println!("Done: {}", done_count);
+    println!("Pending: {}", items.len() - done_count);
+    println!("Completion: {:.1}%", completion);
+
+    if items.iter().any(|item| item.due.is_some()) {
+        let overdue = items.iter().filter(|item| item.is_overdue(today)).count();
+        println!("Overdue: {}", overdue);
+    }
+
+    if items.iter().any(|item| item.completed.is_some()) {
+        let completed_today = items.iter().filter(|item| item.done && item.completed == Some(today)).count();
+        println!("Completed today: {}", completed_today);
+    }
+
+    if since.is_some() || until.is_some() {
+        let in_range = completed_between(items, since, until);
+        let range = match (since, until) {
+            (Some(since), Some(until)) => format!("from {} to {}", since, until),
+            (Some(since), None) => format!("since {}", since),
+            (None, Some(until)) => format!("until {}", until),
+            (None, None) => unreachable!()
+        };
+        println!("Completed {}: {}", range, in_range);
+    }
+}
+
+// Count the done items checked off within `since` and `until`, both inclusive, items without a `done:` date never count
+fn completed_between(items: &[TodoItem], since: Option<Date>, until: Option<Date>) -> usize {
+    items.iter()
+        .filter(|item| item.done)
+        .filter_map(|item| item.completed)
+        .filter(|&completed| since.is_none_or(|since| completed >= since) && until.is_none_or(|until| completed <= until))
+        .count()
+}
+
+// Filter, sort and limit the list for `list`, then print it
+fn list_items(list: &TodoList, args: &ListArgs, options: &Options) -> Result<()> {
+    let mut items: Vec<TodoItem> = list.list()?
+        .into_iter()
+        .filter(|item| (!args.done || item.done) && (!args.pending || !item.done))
+        .filter(|item| args.project.as_ref().is_none_or(|project| has_tag(&item.projects, project, '+')))
+        .filter(|item| args.context.as_ref().is_none_or(|context| has_tag(&item.contexts, context, '@')))
+        .filter(|item| args.due_before.is_none_or(|before| item.due.is_some_and(|due| due <= before)))
+        .filter(|item| args.due_after.is_none_or(|after| item.due.is_some_and(|due| due >= after)))
+        .collect();
+    sort_items(&mut items, &args.sort);
+
+    let (done, total) = (items.iter().filter(|item| item.done).count(), items.len());
+
+    // Piped output never carries the escape code, it would end up in whatever reads it
+    if (args.set_title || args.reset_title) && stdout().is_terminal() {
+        let title = if args.set_title { format!("todo: {}/{} done", done, total) } else { String::new() };
+        print!("{}", terminal_title(&title));
+    }
+
+    if args.reverse {
+        items.reverse();
+    }
+
+    let hidden = match args.limit {
+        Some(limit) if items.len() > limit => {
+            let hidden = items.len() - limit;
+            items.truncate(limit);
+            hidden
+        },
+        _ => 0
+    };
+
+    if args.json {
+        println!("{}", items_to_json(&items));
+        return Ok(());
+    }
+
+    if args.plain {
+        for item in &items {
+            println!("{}", export_item(item, ExportFormat::Plain));
+        }
+        return Ok(());
+    }
+
+    // The bar covers every matching item, including any cut off by `--limit`
+    // ^ It's left out when filtering by done state, since it could only ever read 0% or 100%
+    if !args.no_progress && !args.compact && !args.done && !args.pending && total > 0 {
+        println!("{}", progress_bar(done, total));
+    }
+
+    // Without a known width there is nothing to wrap to, so the lines are printed whole
+    let render = RenderOptions {
+        color: options.color,
+        show_dates: args.show_dates,
+        notes: args.notes,
+        wrap: if args.wrap { terminal_width() } else { None },
+        compact: args.compact,
+        title: match args.compact {
+            true => String::new(),
+            false => args.header.clone().or_else(|| CONFIG.list_header.clone()).unwrap_or_else(|| DEFAULT_TITLE.to_string())
+        }
+    };
+
+    match args.group_by {
+        Some(key) => print_groups(&items, key, &render, hidden),
+        None => print_list(&items, &render, hidden)
+    }
+
+    // Like the bar, the counts are taken after filtering but before `--limit`
+    if !args.no_footer && !args.compact && total > 0 {
+        let noun = if total == 1 { "item" } else { "items" };
+        println!("{} {} ({} pending, {} done)", total, noun, total - done, done);
+    }
+
+    if args.legend {
+        print_legend(options.color);
+    }
+
+    Ok(())
+}
+
+// The OSC escape code setting the window or tab title of most terminals, an empty title resets it to the default
+fn terminal_title(title: &str) -> String {
+    format!("\x1b]0;{}\x07", title)
+}
+
+// Format an item for `list --compact`, i.e. `3. ✓ (A) text due:2025-06-01`, done items are dimmed with color
+// ^ Overdue pending items are marked with the overdue symbol instead, due dates are printed without how far off they are
+fn format_compact(item: &TodoItem, color: bool) -> String {
+    let symbols = symbols();
+    let mark = match (item.done, item.is_overdue(Date::today())) {
+        (true, _) => symbols.compact_done,
+        (false, true) => symbols.overdue,
+        (false, false) => symbols.compact_pending
+    };
+
+    let mut line = format!("{}. {} ", item.index, mark);
+    if let Some(priority) = item.priority {
+        line.push_str(&format!("({}) ", priority));
+    }
+    line.push_str(&item.display_text());
+    if let Some(due) = item.due {
+        line.push_str(&format!(" due:{}", due));
+    }
+
+    if color && item.done { format!("\x1b[2m{}\x1b[0m", line) } else { line }
+}
+
+// Draw how many items are done as a fixed width bar, i.e. `[■■■■■■□□□□□□□□□□□□□□] 30% (3/10)`
+fn progress_bar(done: usize, total: usize) -> String {
+    const WIDTH: usize = 20;
+    let filled = done * WIDTH / total;
+
+    let symbols = symbols();
+    format!("[{}{}] {}% ({}/{})", symbols.bar_done.repeat(filled), symbols.bar_pending.repeat(WIDTH - filled), done * 100 / total, done, total)
+}
+
+// Print items under the `TODO list:` header, or a friendly message if there are none
+// ^ `hidden` is how many items were cut off by `--limit`, noted after the items
+fn print_list(items: &[TodoItem], render: &RenderOptions, hidden: usize) {
+    let mut parsed_list = render_items(items, render);
+
+    if hidden > 0 {
+        parsed_list.push_str(&format!("... and {} more\n", hidden));
+    }
+
+    if parsed_list.is_empty() {
+        println!("{}", symbols().empty_list);
+    } else if render.compact {
+        print!("{}", parsed_list);
+    } else if render.title.is_empty() {
+        println!("{}", parsed_list);
+    } else {
+        println!("{}\n{}", render.title, parsed_list);
+    }
+}
+
+// The title `print_list` puts above the items unless `--header` or config.toml change it
+const DEFAULT_TITLE: &str = "TODO list:";
+
+// How `render_items` formats items and `print_list` and `print_groups` title them, everything but the title is off by default
+struct RenderOptions {
+    color: bool,
+    // Print when each item was created and checked off
+    show_dates: bool,
+    // Print each item's notes indented beneath it
+    notes: bool,
+    // Continue lines longer than this many columns on the next line, aligned under the text
+    wrap: Option<usize>,
+    // Print each item as `format_compact` does and no blank line after them
+    compact: bool,
+    // Printed above the items, an empty title prints no line at all
+    title: String
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions { color: false, show_dates: false, notes: false, wrap: None, compact: false, title: DEFAULT_TITLE.to_string() }
+    }
+}
+
+// Format parsed items one per line, the string `list` prints below its header
+// ^ Takes owned items or references, so groups of borrowed items render the same way as the whole list
+fn render_items<T: Borrow<TodoItem>>(items: &[T], render: &RenderOptions) -> String {
+    let wrapped = |line: String, indent: usize| match render.wrap {
+        Some(width) => wrap_line(&line, width, indent),
+        None => line
+    };
+
+    let mut formatted = String::new();
+    for item in items.iter().map(Borrow::borrow) {
+        let prefix = format!("{}. ", item.index);
+        let line = if render.compact { format_compact(item, render.color) } else { format_item(item, render.color, render.show_dates) };
+        formatted.push_str(&wrapped(line, prefix.chars().count()));
+        formatted.push('\n');
+
+        if render.notes {
+            for note in &item.notes {
+                formatted.push_str(&wrapped(format!("    {}", note), 4));
+                formatted.push('\n');
+            }
+        }
+    }
+    formatted
+}
+
+// Wrap a formatted line at spaces so no part of it is wider than `width`, indenting continuations by `indent`
+// ^ Escape codes take up no columns, the ones still active at a break are closed before it and reopened after
+//   the indent, so a struck through item stays struck through on every line. Words too long for a line stay whole
+fn wrap_line(line: &str, width: usize, indent: usize) -> String {
+    let mut wrapped = String::new();
+    // The parameters of every code still in effect, i.e. `9`, `2` and `36` inside a struck through `+project`
+    let mut active: Vec<&str> = Vec::new();
+    let mut column = 0;
+
+    for (i, word) in line.split(' ').enumerate() {
+        let word_width = visible_width(word);
+
+        if i > 0 && column > indent && column + 1 + word_width > width {
+            if !active.is_empty() {
+                wrapped.push_str("\x1b[0m");
+            }
+            wrapped.push('\n');
+            wrapped.push_str(&" ".repeat(indent));
+            if !active.is_empty() {
+                wrapped.push_str(&format!("\x1b[{}m", active.join(";")));
+            }
+            column = indent;
+        } else if i > 0 {
+            wrapped.push(' ');
+            column += 1;
+        }
+
+        wrapped.push_str(word);
+        column += word_width;
+
+        // A reset ends everything before it, a default foreground ends the colors before it, anything else adds to what's active
+        for code in escape_codes(word) {
+            for param in code[2..code.len() - 1].split(';') {
+                match param {
+                    "" | "0" => active.clear(),
+                    "39" => active.retain(|active| !matches!(active.parse::<u8>(), Ok(30..=37 | 90..=97))),
+                    _ => active.push(param)
+                }
+            }
+        }
+    }
+
+    wrapped
+}
+
+// Get every `ESC [ ... m` color code in some text, in order
+fn escape_codes(text: &str) -> Vec<&str> {
+    let mut codes = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("\x1b[") {
+        let Some(end) = rest[start..].find('m') else {
+            break;
+        };
+        codes.push(&rest[start..start + end + 1]);
+        rest = &rest[start + end + 1..];
+    }
+    codes
+}
+
+// Count the columns some text takes up on a terminal, leaving out color codes
+fn visible_width(text: &str) -> usize {
+    let codes: usize = escape_codes(text).iter().map(|code| code.chars().count()).sum();
+    text.chars().count() - codes
+}
+
+// Get the terminal width from `COLUMNS`, or by asking `stty` about the controlling terminal
+// ^ None when neither works, i.e. under a pipe with no terminal attached or on Windows without `COLUMNS`
+fn terminal_width() -> Option<usize> {
+    if let Some(columns) = var_os("COLUMNS").and_then(|columns| columns.to_str()?.trim().parse().ok()) {
+        return Some(columns).filter(|&columns| columns > 0);
+    }
+
+    if !cfg!(unix) {
+        return None;
+    }
+
+    let tty = File::open("/dev/tty").ok()?;
+    let output = std::process::Command::new("stty").arg("size").stdin(tty).stderr(Stdio::null()).output().ok()?;
+    let size = String::from_utf8(output.stdout).ok()?;
+    size.split_whitespace().nth(1)?.parse().ok().filter(|&columns| columns > 0)
+}
+
+// Print items clustered under a header per group, in the order each group first appears
+// ^ Items without a tag of the requested kind go under `(none)`, printed last, all of it under the same title as `print_list`
+fn print_groups(items: &[TodoItem], key: GroupKey, render: &RenderOptions, hidden: usize) {
+    if items.is_empty() {
+        println!("{}", symbols().empty_list);
+        return;
+    }
+
+    let mut groups: Vec<(String, Vec<&TodoItem>)> = Vec::new();
+    let mut ungrouped = Vec::new();
+
+    for item in items {
+        let names = match key {
+            GroupKey::Project => item.projects.iter().map(|project| format!("+{}", project)).collect(),
+            GroupKey::Context => item.contexts.iter().map(|context| format!("@{}", context)).collect(),
+            GroupKey::Done => vec![if item.done { "Done" } else { "Pending" }.to_string()]
+        };
+
+        if names.is_empty() {
+            ungrouped.push(item);
+        }
+        for name in names {
+            match groups.iter_mut().find(|(existing, _)| *existing == name) {
+                Some((_, group)) => group.push(item),
+                None => groups.push((name, vec![item]))
+            }
+        }
+    }
+
+    if !ungrouped.is_empty() {
+        groups.push(("(none)".to_string(), ungrouped));
+    }
+
+    if !render.title.is_empty() {
+        println!("{}", render.title);
+    }
+
+    for (name, group) in &groups {
+        println!("{}:\n{}", name, render_items(group, render));
+    }
+
+    if hidden > 0 {
+        println!("... and {} more", hidden);
+    }
+}
+
+// Read indices from stdin in a loop, toggling each item's done state and redrawing the list
+fn interactive_mode(list: &mut TodoList, options: &Options) -> Result<()> {
+    loop {
+        let items = list.list()?;
+        print_list(&items, &RenderOptions { color: options.color, ..RenderOptions::default() }, 0);
+
+        print!("Toggle item (empty or `q` to quit): ");
+        stdout().flush()?;
+
+        let mut answer = String::new();
+        if stdin().read_line(&mut answer)? == 0 {
+            return Ok(());
+        }
+
+        let answer = answer.trim();
+        if answer.is_empty() || answer.eq_ignore_ascii_case("q") {
+            return Ok(());
+        }
+
+        match answer.parse::<usize>().ok().and_then(|index| items.iter().find(|item| item.index == index)) {
+            Some(item) => {
+                list.toggle(&[item.index])?;
+            },
+            None => eprintln!("`{}` doesn't match any item in your TODO list.", answer)
+        }
+    }
+}
+
+// Format an item for `export`, the checkbox is the only trace of its done state
+fn export_item(item: &TodoItem, format: ExportFormat) -> String {
+    let checkbox = if item.done { "[x]" } else { "[ ]" };
+
+    match format {
+        ExportFormat::Plain => format!("{} {}", checkbox, item.display_text()),
+        ExportFormat::Markdown => format!("- {} {}", checkbox, item.display_text())
+    }
+}
+
+// Serialize items as a JSON array, i.e. `[{"index":1,"text":"milk","done":false}]`
+fn items_to_json(items: &[TodoItem]) -> String {
+    let objects: Vec<String> = items.iter().map(item_to_json).collect();
+    format!("[{}]", objects.join(","))
+}
+
+// Serialize a single item as a JSON object, missing values become `null`
+fn item_to_json(item: &TodoItem) -> String {
+    let fields = [
+        ("index", item.index.to_string()),
+        ("text", json_string(&item.display_text())),
+        ("done", item.done.to_string()),
+        ("priority", item.priority.map_or("null".to_string(), |priority| json_string(&priority.to_string()))),
+        ("due", item.due.map_or("null".to_string(), |due| json_string(&due.to_string()))),
+        ("label", item.label.as_deref().map_or("null".to_string(), json_string)),
+        ("created", item.created.map_or("null".to_string(), |created| json_string(&created.to_string()))),
+        ("completed", item.completed.map_or("null".to_string(), |completed| json_string(&completed.to_string()))),
+        ("projects", json_array(&item.projects)),
+        ("contexts", json_array(&item.contexts)),
+        ("notes", json_array(&item.notes))
+    ];
+
+    let fields: Vec<String> = fields.iter()
+        .map(|(key, value)| format!("\"{}\":{}", key, value))
+        .collect();
+
+    format!("{{{}}}", fields.join(","))
+}
+
+// Quote and escape a JSON string
+fn json_string(text: &str) -> String {
+    format!("\"{}\"", escape_json(text))
+}
+
+// Serialize strings as a JSON array of strings
+fn json_array(values: &[String]) -> String {
+    let values: Vec<String> = values.iter().map(|value| json_string(value)).collect();
+    format!("[{}]", values.join(","))
+}
+
+// Escape a string so it can be placed between double quotes in JSON
+fn escape_json(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+// Append every non-empty line of a file or stdin, stripping any existing `N.` numbering
+// ^ JSON input is parsed in full first, so malformed JSON or a bad item fails before anything is written
+fn import_items(list: &mut TodoList, source: &Path, format: Option<ImportFormat>) -> Result<usize> {
+    let content = if source == Path::new("-") {
+        read_to_string(stdin())?
+    } else {
+        fs::read_to_string(source).map_err(|e| {
+            Error::new(ErrorKind::InvalidInput, format!("Cannot read {}: {}", source.display(), e))
+        })?
+    };
+
+    let format = format.unwrap_or(if looks_like_json(&content) { ImportFormat::Json } else { ImportFormat::Text });
+    if format == ImportFormat::Json {
+        let mut items = json_items(&content)?;
+        list.append_items(&mut items)?;
+        return Ok(items.len());
+    }
+
+    let messages: Vec<String> = content.lines()
+        .map(strip_index)
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.to_string())
+        .collect();
+
+    if !messages.is_empty() {
+        list.add(&messages, None, true)?;
+    }
+
+    Ok(messages.len())
+}
+
+// Whether import input is a JSON array rather than lines, an exported `[x] item` line also starts with `[`
+fn looks_like_json(content: &str) -> bool {
+    content.trim_start().strip_prefix('[').is_some_and(|rest| rest.trim_start().starts_with(['{', ']']))
+}
+
+// Turn a JSON array of items into items to append, the way `list --json` printed them
+// ^ `text` is the item's text as `list --json` shows it, so words in it that look like metadata are escaped
+//   to stay text. A `+` or `@` word missing from `projects` or `contexts` was escaped when it was exported
+//   and is escaped again, without those arrays every tag in the text counts. `index` is ignored
+fn json_items(content: &str) -> Result<Vec<TodoItem>> {
+    let JsonValue::Array(values) = parse_json(content)? else {
+        return Err(Error::new(ErrorKind::InvalidInput, "Cannot import JSON: expected an array of items"));
+    };
+
+    values.iter().enumerate().map(|(i, value)| {
+        let invalid = |reason: &str| Error::new(ErrorKind::InvalidInput, format!("Cannot import JSON item {}: {}", i + 1, reason));
+        let JsonValue::Object(fields) = value else {
+            return Err(invalid("expected an object"));
+        };
+        let field = |key: &str| fields.iter().find(|(name, _)| name == key).map(|(_, value)| value).filter(|value| **value != JsonValue::Null);
+        let string = |key: &str| match field(key) {
+            Some(JsonValue::String(value)) => Ok(Some(value.as_str())),
+            Some(_) => Err(invalid(&format!("`{}` must be a string", key))),
+            None => Ok(None)
+        };
+        let date = |key: &str| string(key)?
+            .map(|value| Date::parse(value).ok_or_else(|| invalid(&format!("`{}` must be a YYYY-MM-DD date", key))))
+            .transpose();
+        let strings = |key: &str| match field(key) {
+            Some(JsonValue::Array(values)) => values.iter().map(|value| match value {
+                JsonValue::String(value) => Ok(value.clone()),
+                _ => Err(invalid(&format!("`{}` must be an array of strings", key)))
+            }).collect::<Result<Vec<String>>>().map(Some),
+            Some(_) => Err(invalid(&format!("`{}` must be an array of strings", key))),
+            None => Ok(None)
+        };
+
+        let text = string("text")?.filter(|text| !text.trim().is_empty()).ok_or_else(|| invalid("`text` must be a non-empty string"))?;
+        let done = match field("done") {
+            Some(JsonValue::Bool(done)) => *done,
+            Some(_) => return Err(invalid("`done` must be true or false")),
+            None => false
+        };
+        let priority = string("priority")?.map(|priority| match parse_priority(priority) {
+            Ok(Some(priority)) => Ok(priority),
+            _ => Err(invalid("`priority` must be a letter A-Z"))
+        }).transpose()?;
+        let label = string("label")?.map(|label| match LABEL_COLORS.contains(&label) {
+            true => Ok(label.to_string()),
+            false => Err(invalid(&format!("`label` must be one of {}", LABEL_COLORS.join(", "))))
+        }).transpose()?;
+
+        let (projects, contexts) = (strings("projects")?, strings("contexts")?);
+        let is_tag = |word: &str| match (word.strip_prefix('+').filter(|tag| !tag.is_empty()), word.strip_prefix('@').filter(|tag| !tag.is_empty())) {
+            (Some(tag), _) => projects.as_ref().is_none_or(|projects| projects.iter().any(|project| project == tag)),
+            (_, Some(tag)) => contexts.as_ref().is_none_or(|contexts| contexts.iter().any(|context| context == tag)),
+            _ => true
+        };
+        let text: Vec<String> = escape_text(text).split(' ')
+            .map(|word| if is_tag(word) { word.to_string() } else { format!("\\{}", word) })
+            .collect();
+        let notes = strings("notes")?.unwrap_or_default().iter()
+            .map(|note| collapse_whitespace(note))
+            .filter(|note| !note.is_empty())
+            .collect();
+
+        // Parsing the stored line picks the tags up again, so they're left empty here
+        Ok(TodoItem {
+            index: 1,
+            text: text.join(" "),
+            done,
+            priority,
+            due: date("due")?,
+            label,
+            created: date("created")?,
+            completed: date("completed")?,
+            projects: Vec::new(),
+            contexts: Vec::new(),
+            notes
+        })
+    }).collect()
+}
+
+// A parsed JSON value, objects keep their keys in order
+#[derive(Debug, PartialEq)]
+enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>)
+}
+
+// Parse a whole JSON document, an error names the line and column it stopped at
+fn parse_json(content: &str) -> Result<JsonValue> {
+    let mut parser = JsonParser { content, position: 0 };
+    let value = parser.value().and_then(|value| {
+        parser.skip_whitespace();
+        match parser.position == content.len() {
+            true => Ok(value),
+            false => Err("unexpected text after the JSON value")
+        }
+    });
+
+    value.map_err(|reason| {
+        let before = &content[..parser.position];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().map_or(0, |last| last.chars().count()) + 1;
+        Error::new(ErrorKind::InvalidInput, format!("Cannot import JSON: {} at line {}, column {}", reason, line, column))
+    })
+}
+
+// A recursive descent JSON parser over `content`, `position` is the byte offset it has read up to
+struct JsonParser<'a> {
+    content: &'a str,
+    position: usize
+}
+
+impl JsonParser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.content[self.position..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.content[self.position..];
+        self.position += rest.len() - rest.trim_start_matches([' ', '\t', '\n', '\r']).len();
+    }
+
+    // Consume `expected` after any whitespace
+    fn expect(&mut self, expected: char) -> std::result::Result<(), &'static str> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.position += c.len_utf8();
+                Ok(())
+            },
+            _ => Err(match expected {
+                ':' => "expected `:`",
+                '"' => "expected a string",
+                _ => "unexpected character"
+            })
+        }
+    }
+
+    fn value(&mut self) -> std::result::Result<JsonValue, &'static str> {
+        self.skip_whitespace();
+        let rest = &self.content[self.position..];
+
+        for (word, value) in [("null", JsonValue::Null), ("true", JsonValue::Bool(true)), ("false", JsonValue::Bool(false))] {
+            if rest.starts_with(word) {
+                self.position += word.len();
+                return Ok(value);
+            }
+        }
+
+        match self.peek() {
+            Some('"') => self.string().map(JsonValue::String),
+            Some('[') => self.array(),
+            Some('{') => self.object(),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some(_) => Err("unexpected character"),
+            None => Err("unexpected end of input")
+        }
+    }
+
+    fn array(&mut self) -> std::result::Result<JsonValue, &'static str> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.position += 1;
+            return Ok(JsonValue::Array(values));
+        }
+
+        loop {
+            values.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.position += 1,
+                Some(']') => {
+                    self.position += 1;
+                    return Ok(JsonValue::Array(values));
+                },
+                _ => return Err("expected `,` or `]`")
+            }
+        }
+    }
+
+    fn object(&mut self) -> std::result::Result<JsonValue, &'static str> {
+        self.expect('{')?;
+        let mut fields = Vec::new();
+
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.position += 1;
+            return Ok(JsonValue::Object(fields));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(':')?;
+            fields.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.position += 1,
+                Some('}') => {
+                    self.position += 1;
+                    return Ok(JsonValue::Object(fields));
+                },
+                _ => return Err("expected `,` or `}`")
+            }
+        }
+    }
+
+    // Read a quoted string, decoding escapes including `\u` surrogate pairs
+    fn string(&mut self) -> std::result::Result<String, &'static str> {
+        self.expect('"')?;
+        let mut string = String::new();
+
+        loop {
+            let Some(c) = self.peek() else {
+                return Err("unterminated string");
+            };
+            self.position += c.len_utf8();
+
+            match c {
+                '"' => return Ok(string),
+                '\\' => {
+                    let Some(escape) = self.peek() else {
+                        return Err("unterminated string");
+                    };
+                    self.position += escape.len_utf8();
+                    string.push(match escape {
+                        '"' => '"',
+                        '\\' => '\\',
+                        '/' => '/',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'u' => self.unicode_escape()?,
+                        _ => return Err("invalid escape in string")
+                    });
+                },
+                c if (c as u32) < 0x20 => return Err("control character in string"),
+                c => string.push(c)
+            }
+        }
+    }
+
+    // Decode the digits after `\u`, joining a high surrogate with the `\uXXXX` low surrogate after it
+    fn unicode_escape(&mut self) -> std::result::Result<char, &'static str> {
+        let first = self.hex_digits()?;
+        let code = match first {
+            0xD800..=0xDBFF => {
+                if !self.content[self.position..].starts_with("\\u") {
+                    return Err("unpaired surrogate in string");
+                }
+                self.position += 2;
+                let second = self.hex_digits()?;
+                if !(0xDC00..=0xDFFF).contains(&second) {
+                    return Err("unpaired surrogate in string");
+                }
+                0x10000 + ((first - 0xD800) << 10) + (second - 0xDC00)
+            },
+            code => code
+        };
+        char::from_u32(code).ok_or("unpaired surrogate in string")
+    }
+
+    fn hex_digits(&mut self) -> std::result::Result<u32, &'static str> {
+        let digits = self.content.get(self.position..self.position + 4).ok_or("invalid `\\u` escape")?;
+        let code = u32::from_str_radix(digits, 16).map_err(|_| "invalid `\\u` escape")?;
+        self.position += 4;
+        Ok(code)
+    }
+
+    fn number(&mut self) -> std::result::Result<JsonValue, &'static str> {
+        let rest = &self.content[self.position..];
+        let length = rest.find(|c: char| !(c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))).unwrap_or(rest.len());
+        let number = rest[..length].parse().map_err(|_| "invalid number")?;
+        self.position += length;
+        Ok(JsonValue::Number(number))
+    }
+}
+
+// Run $EDITOR on the list and wait for it, falling back to `vi`, or `notepad` on Windows
+// ^ $EDITOR can carry arguments, i.e. `code --wait`, the path is passed after them
+fn open_in_editor(path: &Path) -> Result<()> {
+    let configured = var_os("EDITOR")
+        .map(|editor| editor.to_string_lossy().trim().to_string())
+        .filter(|editor| !editor.is_empty());
+    let fallback = if cfg!(windows) { "notepad" } else { "vi" };
+    let editor = configured.as_deref().unwrap_or(fallback);
+
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or(fallback);
+    let status = std::process::Command::new(program).args(words).arg(path).status().map_err(|e| {
+        match (e.kind(), &configured) {
+            (ErrorKind::NotFound, Some(_)) => Error::new(
+                ErrorKind::InvalidInput,
+                format!("Cannot find `{}` from $EDITOR, set it to an installed editor", program)
+            ),
+            (ErrorKind::NotFound, None) => Error::new(
+                ErrorKind::InvalidInput,
+                format!("No editor is configured and `{}` isn't installed, set $EDITOR to the editor to use", fallback)
+            ),
+            _ => e
+        }
+    })?;
+
+    if !status.success() {
+        return Err(Error::other(format!("`{}` failed with {}, the list was left as the editor saved it", editor, status)));
+    }
+    Ok(())
+}
+
+// Get the path done items are archived to, `done.txt` next to `todo.txt` and `<name>.done.txt`
+// ^ next to any other list, so named lists in the same folder never share an archive
+fn archive_path(path: &Path) -> PathBuf {
+    match path.file_stem().and_then(|stem| stem.to_str()) {
+        Some(stem) if stem != "todo" => path.with_file_name(format!("{}.done.txt", stem)),
+        _ => path.with_file_name("done.txt"),
+    }
+}
+
+// The date an item has to be checked off before to be older than `--older-than` days
+fn completed_cutoff(days: usize) -> Date {
+    Date::today().add_days(-(days as i64))
+}
+
+// Find which of the requested indices didn't match any item
+fn missing_indices(requested: &[usize], marked: &Marked) -> Vec<usize> {
+    requested.iter().filter(|index| !marked.moved.contains_key(index)).copied().collect()
+}
+
+// Parse index arguments, expanding ranges like `2-4` and dropping duplicates
+// ^ Ranges stop at the highest numbered item, so `1-1000000` doesn't expand into a million indices
+fn parse_indices(values: &[String], list: &TodoList) -> Result<Vec<usize>> {
+    let mut indices = Vec::new();
+    let mut seen = HashSet::new();
+    let mut highest = None;
+
+    for value in values {
+        let invalid = || Error::new(ErrorKind::InvalidInput, format!("Expected a number, range like `2-4`, `first` or `last` for <indices>, got '{}'", value));
+        let bound = |bound: &str| match resolve_keyword(bound, list)? {
+            Some(index) => Ok(index),
+            None => bound.trim().parse::<usize>().map_err(|_| invalid())
+        };
+
+        let (start, end) = match value.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (bound(start)?, bound(end)?);
+                if start > end {
+                    return Err(invalid());
+                }
+
+                // An index past the end is still kept once, so it's reported as not found
+                let highest = match highest {
+                    Some(highest) => highest,
+                    None => *highest.insert(list.list()?.iter().map(|item| item.index).max().unwrap_or(0))
+                };
+                (start, end.min(highest.max(start)))
+            },
+            None => {
+                let index = bound(value)?;
+                (index, index)
+            }
+        };
+
+        indices.extend((start..=end).filter(|index| seen.insert(*index)));
+    }
+
+    Ok(indices)
+}
+
+// Parse a single index argument, either a number or `first`/`last`
+fn parse_index(value: &str, list: &TodoList) -> Result<usize> {
+    match resolve_keyword(value, list)? {
+        Some(index) => Ok(index),
+        None => value.trim().parse::<usize>().map_err(|_| {
+            Error::new(ErrorKind::InvalidInput, format!("Expected a number, `first` or `last` for <index>, got '{}'", value))
+        })
+    }
+}
+
+// Resolve `first` and `last` to the lowest and highest numbered item, anything else is left to the caller
+// ^ Only reads the list when a keyword was actually given
+fn resolve_keyword(value: &str, list: &TodoList) -> Result<Option<usize>> {
+    let value = value.trim();
+    let first = value.eq_ignore_ascii_case("first");
+    if !first && !value.eq_ignore_ascii_case("last") {
+        return Ok(None);
+    }
+
+    let indices = list.list()?.into_iter().map(|item| item.index);
+    let index = if first { indices.min() } else { indices.max() };
+    match index {
+        Some(index) => Ok(Some(index)),
+        None => Err(Error::new(ErrorKind::InvalidInput, format!("Your TODO list is empty, there is no {} item", value.to_lowercase())))
+    }
+}
+
+// Parse a due date argument, `YYYY-MM-DD`, a date relative to today or `none` to clear it
+// ^ Relative dates are resolved here, so the stored date stays put instead of moving along with today
+fn parse_due_date(value: &str) -> Result<Option<Date>> {
+    if value.trim().eq_ignore_ascii_case("none") {
+        return Ok(None);
+    }
+
+    match Date::parse(value.trim()).or_else(|| Date::parse_relative(value, Date::today())) {
+        Some(date) => Ok(Some(date)),
+        None => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("`{}` is not a valid date, use i.e. 2025-06-01, today, tomorrow, +3d, +2w, +1m, friday, next monday or none", value)
+        ))
+    }
+}
+
+// Parse a numeric flag value for clap, replacing the ParseIntError wording with something readable
+fn parse_number_arg(value: &str) -> std::result::Result<usize, String> {
+    value.trim().parse().map_err(|_| format!("Expected a number, got '{}'", value))
+}
+
+// Parse a `YYYY-MM-DD` flag value for clap, so a bad date is rejected before the list is read
+fn parse_date_arg(value: &str) -> std::result::Result<Date, String> {
+    Date::parse(value).ok_or_else(|| format!("`{}` is not a valid date, use YYYY-MM-DD", value))
+}
+
+// Parse a priority argument, either a letter A-Z or `none` to clear it
+fn parse_priority(value: &str) -> Result<Option<char>> {
+    if value.eq_ignore_ascii_case("none") {
+        return Ok(None);
+    }
+
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(letter), None) if letter.is_ascii_alphabetic() => Ok(Some(letter.to_ascii_uppercase())),
+        _ => Err(Error::new(ErrorKind::InvalidInput, format!("`{}` is not a valid priority, use a letter A-Z or `none`", value)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_line_keeps_styles_across_breaks() {
+        let line = "\x1b[9;2maaaa bbbb cccc\x1b[0m";
+        assert_eq!(wrap_line(line, 10, 2), "\x1b[9;2maaaa bbbb\x1b[0m\n  \x1b[9;2mcccc\x1b[0m");
+    }
+
+    #[test]
+    fn wrap_line_drops_closed_tag_colors() {
+        let line = "aaaa \x1b[36m+proj\x1b[39m bbbb cccc";
+        assert_eq!(wrap_line(line, 16, 2), "aaaa \x1b[36m+proj\x1b[39m bbbb\n  cccc");
+
+        let line = "\x1b[9maaaa \x1b[36m+proj\x1b[39m bbbb cccc\x1b[0m";
+        assert_eq!(wrap_line(line, 16, 2), "\x1b[9maaaa \x1b[36m+proj\x1b[39m bbbb\x1b[0m\n  \x1b[9mcccc\x1b[0m");
+    }
+
+    #[test]
+    fn json_export_imports_back_unchanged() {
+        let content = "1. (A) call \\@home +proj \\+1 due:2026-11-01 col:red created:2026-01-01\n\tring twice\n2. [x] \\(B) x @work created:2026-01-01 done:2026-02-02\n";
+        let mut items = json_items(&items_to_json(&parse_items(content))).unwrap();
+
+        let mut list = TodoList::new(Box::new(InMemoryStorage::new("")));
+        list.append_items(&mut items).unwrap();
+        assert_eq!(list.content().unwrap(), content);
+    }
+
+    #[test]
+    fn json_tags_count_without_tag_arrays() {
+        let items = json_items(r#"[{"text":"call +bob @home"}]"#).unwrap();
+        assert_eq!(items[0].text, "call +bob @home");
+    }
+
+    #[test]
+    fn parse_priority_takes_one_letter_or_none() {
+        assert_eq!(parse_priority("a").unwrap(), Some('A'));
+        assert_eq!(parse_priority("NONE").unwrap(), None);
+        assert!(parse_priority("AB").is_err());
+        assert!(parse_priority("1").is_err());
+    }
+
+    #[test]
+    fn describe_error_names_the_list_in_use() {
+        let path = Path::new("lists/work.txt");
+        let denied = Error::from(ErrorKind::PermissionDenied);
+        assert_eq!(describe_error(&denied, Some(path)), "Cannot write to lists/work.txt: permission denied");
+        assert_eq!(describe_error(&Error::from(ErrorKind::NotFound), Some(path)), "Cannot open lists/work.txt: path not found");
+        assert_eq!(describe_error(&denied, None), "Cannot write to your TODO list: permission denied");
+
+        let usage = Error::new(ErrorKind::InvalidInput, "Item with index 3 not found.");
+        assert_eq!(describe_error(&usage, Some(path)), "Item with index 3 not found.");
+    }
+
+    // Options for running a command without printing confirmations or touching a file
+    fn quiet_options() -> Options {
+        Options { color: false, quiet: true, verbose: false, dry_run: false, stdin: false, stdout: false }
+    }
+
+    // A list kept in memory, starting out with `content`
+    fn list(content: &str) -> TodoList {
+        TodoList::new(InMemoryStorage::new(content))
+    }
+
+    // Parse index arguments as given on the command line
+    fn indices(values: &[&str], list: &TodoList) -> Result<Vec<usize>> {
+        parse_indices(&values.iter().map(|value| value.to_string()).collect::<Vec<String>>(), list)
+    }
+
+    #[test]
+    fn parse_indices_expands_ranges_and_drops_duplicates() {
+        let list = list("1. a\n2. b\n3. c\n4. d\n");
+        assert_eq!(indices(&["3", "1-2", "2"], &list).unwrap(), [3, 1, 2]);
+        assert_eq!(indices(&[" 2 - 3 "], &list).unwrap(), [2, 3]);
+    }
+
+    #[test]
+    fn parse_indices_clamps_ranges_to_the_list() {
+        let list = list("1. a\n2. b\n3. c\n");
+        assert_eq!(indices(&["2-1000000000"], &list).unwrap(), [2, 3]);
+        assert_eq!(indices(&["7-9"], &list).unwrap(), [7]);
+    }
+
+    #[test]
+    fn missing_indices_keeps_the_requested_order() {
+        let mut list = list("1. a\n5. b\n");
+        let marked = list.set_done(&[9, 5, 7, 1], true).unwrap();
+        assert_eq!(missing_indices(&[9, 5, 7, 1], &marked), [9, 7]);
+    }
+
+    #[test]
+    fn unknown_indices_exit_as_usage_errors() {
+        let mut list = list("1. a\n");
+        let error = run(Command::Done { indices: vec!["4".to_string()] }, &mut list, Path::new("todo.txt"), &quiet_options()).unwrap_err();
+        assert_eq!(error.to_string(), "Item with index 4 not found.");
+        assert_eq!(exit_code(&error), ExitCode::from(USAGE_ERROR));
+        assert_eq!(exit_code(&Error::from(ErrorKind::PermissionDenied)), ExitCode::from(IO_ERROR));
+    }
+
+    #[test]
+    fn diff_lines_shows_only_changed_lines() {
+        assert_eq!(diff_lines("1. a\n2. b\n3. c\n", "1. a\n2. [x] b\n3. c\n4. d\n"), "-2. b\n+2. [x] b\n+4. d\n");
+        assert_eq!(diff_lines("1. a\n", "1. a\n"), "");
+    }
+
+    #[test]
+    fn dry_run_clear_never_asks() {
+        let options = Options { dry_run: true, ..quiet_options() };
+        for done in [false, true] {
+            let mut list = list("1. a\n2. [x] b\n");
+            run(Command::Clear { force: false, done }, &mut list, Path::new("todo.txt"), &options).unwrap();
+        }
+    }
+
+    // An empty directory of its own for a test that needs real files, removed again by the test
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("todo-rs-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn check_list_path_rejects_directories_and_dangling_links() {
+        let dir = temp_dir("check-list-path");
+        assert!(check_list_path(&dir.join("todo.txt")).is_ok());
+
+        let error = check_list_path(&dir).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::IsADirectory);
+        assert_eq!(exit_code(&error), ExitCode::from(IO_ERROR));
+        assert!(describe_error(&error, Some(&dir)).contains("exists but is a directory"));
+
+        #[cfg(unix)]
+        {
+            let link = dir.join("link.txt");
+            std::os::unix::fs::symlink(dir.join("missing.txt"), &link).unwrap();
+            assert_eq!(check_list_path(&link).unwrap_err().kind(), ErrorKind::NotFound);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn progress_bar_rounds_down() {
+        assert_eq!(progress_bar(1, 3), "[■■■■■■□□□□□□□□□□□□□□] 33% (1/3)");
+        assert_eq!(progress_bar(2, 2), "[■■■■■■■■■■■■■■■■■■■■] 100% (2/2)");
+    }
+
+    #[test]
+    fn parse_indices_resolves_first_and_last() {
+        let list = list("2. a\n3. b\n7. c\n");
+        assert_eq!(indices(&["first", "LAST"], &list).unwrap(), [2, 7]);
+        assert_eq!(indices(&["first-3"], &list).unwrap(), [2, 3]);
+        assert_eq!(indices(&["3-last"], &list).unwrap(), [3, 4, 5, 6, 7]);
+        assert_eq!(parse_index(" last ", &list).unwrap(), 7);
+    }
+
+    #[test]
+    fn parse_indices_rejects_bad_input() {
+        let list = list("1. a\n");
+        for value in ["3-1", "x", "1-", "-2", "1.5"] {
+            let error = indices(&[value], &list).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidInput, "{} was accepted", value);
+        }
+
+        let error = indices(&["last"], &self::list("")).unwrap_err();
+        assert_eq!(error.to_string(), "Your TODO list is empty, there is no last item");
+    }
+
+    #[test]
+    fn visible_width_skips_escape_codes() {
+        assert_eq!(visible_width("\x1b[9;2mdone\x1b[0m ✓"), 6);
+    }
+
+    #[test]
+    fn stats_window_includes_both_ends() {
+        let items = parse_items("1. [x] a done:2025-05-31\n2. [x] b done:2025-06-01\n3. [x] c done:2025-06-30\n4. [x] d done:2025-07-01\n5. [x] e\n6. f\n");
+        let date = |value| Date::parse(value);
+        assert_eq!(completed_between(&items, date("2025-06-01"), date("2025-06-30")), 2);
+        assert_eq!(completed_between(&items, date("2025-06-01"), None), 3);
+        assert_eq!(completed_between(&items, None, date("2025-06-01")), 2);
+        assert_eq!(completed_between(&items, date("2025-08-01"), None), 0);
+    }
+
+    #[test]
+    fn list_names_stay_safe_file_names() {
+        assert!(valid_list_name("work_2-a"));
+        for name in ["", "../work", "a b", "work.txt", "work.done"] {
+            assert!(!valid_list_name(name), "{:?} was accepted", name);
+        }
+    }
+
+    #[test]
+    fn every_list_gets_its_own_archive() {
+        assert_eq!(archive_path(Path::new("data/todo.txt")), Path::new("data/done.txt"));
+        assert_eq!(archive_path(Path::new("data/lists/work.txt")), Path::new("data/lists/work.done.txt"));
+        assert_eq!(archive_path(Path::new("data/lists/home.txt")), Path::new("data/lists/home.done.txt"));
+    }
+
+    #[test]
+    fn due_dates_resolve_against_today() {
+        assert_eq!(parse_due_date(" None ").unwrap(), None);
+        assert_eq!(parse_due_date("2025-06-01").unwrap(), Date::parse("2025-06-01"));
+        assert_eq!(parse_due_date("tomorrow").unwrap(), Some(Date::today().add_days(1)));
+        assert_eq!(parse_due_date("2025-02-30").unwrap_err().kind(), ErrorKind::InvalidInput);
+        assert_eq!(parse_due_date("someday").unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn search_exits_1_only_when_nothing_matched() {
+        let mut list = list("1. buy milk\n2. [x] call bob\n");
+        let search = |query: &str, done| Command::Search { query: query.to_string(), done, pending: false };
+
+        let error = run(search("eggs", false), &mut list, Path::new("todo.txt"), &quiet_options()).unwrap_err();
+        assert!(is_no_matches(&error));
+        assert_eq!(exit_code(&error), ExitCode::from(NO_MATCHES));
+
+        let error = run(search("milk", true), &mut list, Path::new("todo.txt"), &quiet_options()).unwrap_err();
+        assert_eq!(exit_code(&error), ExitCode::from(NO_MATCHES));
+
+        run(search("MILK", false), &mut list, Path::new("todo.txt"), &quiet_options()).unwrap();
+    }
+
+    #[test]
+    fn due_status_counts_whole_days() {
+        let today = Date::parse("2025-03-01").unwrap();
+        assert_eq!(due_status(today, today), "due today");
+        assert_eq!(due_status(today.add_days(1), today), "due in 1d");
+        assert_eq!(due_status(today.add_days(-1), today), "overdue 1d");
+        // Across a month end
+        assert_eq!(due_status(Date::parse("2025-02-26").unwrap(), today), "overdue 3d");
+        assert_eq!(due_status(Date::parse("2026-03-01").unwrap(), today), "due in 365d");
+    }
+
+    #[test]
+    fn older_than_counts_back_from_today() {
+        assert_eq!(completed_cutoff(0), Date::today());
+        assert_eq!(completed_cutoff(30), Date::today().add_days(-30));
+    }
+
+    #[test]
+    fn parse_json_reads_nested_values() {
+        let value = parse_json(r#" [{"a": [1, -2.5e1, true, null], "b": "x\"\u00e9\n"}] "#).unwrap();
+        assert_eq!(value, JsonValue::Array(vec![JsonValue::Object(vec![
+            ("a".to_string(), JsonValue::Array(vec![JsonValue::Number(1.0), JsonValue::Number(-25.0), JsonValue::Bool(true), JsonValue::Null])),
+            ("b".to_string(), JsonValue::String("x\"é\n".to_string()))
+        ])]));
+    }
+
+    #[test]
+    fn parse_json_reports_where_it_failed() {
+        let error = parse_json("[\n  {\"text\": }]").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert!(error.to_string().contains("at line 2, column 12"), "{}", error);
+        assert!(parse_json("[] x").is_err());
+    }
+
+    #[test]
+    fn looks_like_json_only_accepts_arrays_of_objects() {
+        assert!(looks_like_json(" [ {\"text\": \"a\"}]"));
+        assert!(looks_like_json("[]"));
+        assert!(!looks_like_json("[x] milk"));
+        assert!(!looks_like_json("1. milk"));
+    }
+
+    #[test]
+    fn json_items_escape_metadata_in_text() {
+        let items = json_items(r#"[{"text": "(A) pay due:2025-06-01 later", "done": false, "priority": "b", "notes": [" call  first "]}]"#).unwrap();
+        assert_eq!(items[0].text, "\\(A) pay \\due:2025-06-01 later");
+        assert_eq!(items[0].priority, Some('B'));
+        assert_eq!(items[0].notes, ["call first"]);
+    }
+
+    #[test]
+    fn json_items_reject_bad_fields() {
+        for (content, reason) in [
+            ("{}", "Cannot import JSON: expected an array of items"),
+            ("[1]", "Cannot import JSON item 1: expected an object"),
+            (r#"[{"text": " "}]"#, "Cannot import JSON item 1: `text` must be a non-empty string"),
+            (r#"[{"text": "a", "done": "yes"}]"#, "Cannot import JSON item 1: `done` must be true or false"),
+            (r#"[{"text": "a"}, {"text": "b", "due": "tomorrow"}]"#, "Cannot import JSON item 2: `due` must be a YYYY-MM-DD date"),
+            (r#"[{"text": "a", "label": "pink"}]"#, "Cannot import JSON item 1: `label` must be one of red, green, yellow, blue, magenta, cyan"),
+            (r#"[{"text": "a", "notes": [1]}]"#, "Cannot import JSON item 1: `notes` must be an array of strings")
+        ] {
+            assert_eq!(json_items(content).unwrap_err().to_string(), reason);
+        }
+    }
+
+    #[test]
+    fn color_choice_overrides_the_terminal_check() {
+        // Test output is captured, so stdout isn't a terminal here and `auto` would be off
+        assert!(ColorChoice::Always.enabled());
+        assert!(!ColorChoice::Never.enabled());
+    }
+
+    #[test]
+    fn rm_by_project_or_text_removes_every_match() {
+        let content = "1. plan +Work\n2. book +workshop\n3. Call bob +work\n4. call alice\n";
+        let rm = |project: Option<&str>, matching: Option<&str>| Command::Rm {
+            index: None,
+            project: project.map(str::to_string),
+            matching: matching.map(str::to_string),
+            force: true
+        };
+
+        let mut by_project = list(content);
+        run(rm(Some("work"), None), &mut by_project, Path::new("todo.txt"), &quiet_options()).unwrap();
+        assert_eq!(by_project.content().unwrap(), "1. book +workshop\n2. call alice\n");
+
+        let mut by_text = list(content);
+        run(rm(None, Some("CALL")), &mut by_text, Path::new("todo.txt"), &quiet_options()).unwrap();
+        assert_eq!(by_text.content().unwrap(), "1. plan +Work\n2. book +workshop\n");
+
+        let mut no_match = list(content);
+        run(rm(Some("home"), None), &mut no_match, Path::new("todo.txt"), &quiet_options()).unwrap();
+        run(rm(None, Some("dentist")), &mut no_match, Path::new("todo.txt"), &quiet_options()).unwrap();
+        assert_eq!(no_match.content().unwrap(), content);
+        assert!(no_match.remove_where(|_| false).unwrap().is_empty());
+        // Nothing was written, so there's no backup to undo to
+        assert_eq!(no_match.undo().unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn sort_items_breaks_ties_with_later_keys() {
+        let mut items = parse_items("1. [x] (A) a\n2. (B) b due:2025-06-02\n3. (B) c due:2025-06-01\n4. d\n5. (A) e\n");
+        sort_items(&mut items, &[SortKey::Priority, SortKey::Due]);
+        assert_eq!(items.iter().map(|item| item.index).collect::<Vec<usize>>(), [1, 5, 3, 2, 4]);
+
+        sort_items(&mut items, &[SortKey::Done, SortKey::Index]);
+        assert_eq!(items.iter().map(|item| item.index).collect::<Vec<usize>>(), [2, 3, 4, 5, 1]);
+    }
+
+    #[test]
+    fn format_compact_prints_one_short_line() {
+        let items = parse_items("1. (A) call \\@bob due:2999-01-01 col:red created:2025-01-01\n2. [x] milk done:2025-01-02\n3. pay rent due:2000-01-01\n");
+        assert_eq!(format_compact(&items[0], false), "1. · (A) call @bob due:2999-01-01");
+        assert_eq!(format_compact(&items[1], false), "2. ✓ milk");
+        assert_eq!(format_compact(&items[1], true), "\x1b[2m2. ✓ milk\x1b[0m");
+        assert_eq!(format_compact(&items[2], false), "3. ⚠ pay rent due:2000-01-01");
+    }
+}